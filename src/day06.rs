@@ -1,25 +1,156 @@
-use std::fs;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
 
-pub fn run() {
-    println!("After 80 days, there are {} fish", calculate_fish_for(80));
-    println!("After 256 days, there are {} fish", calculate_fish_for(256));
+use crate::matrix::{accumulate, Matrix};
+
+// Days between one spawn and the next, and the extra days a newborn waits before its first cycle
+const SPAWN_CYCLE: usize = 7;
+const NEWBORN_DELAY: usize = 2;
+
+// Counts modulo this are used once exact counts no longer fit
+const FAR_MODULUS: u128 = 1_000_000_007;
+
+const SERIES_PATH: &str = "output/day06_population.csv";
+
+// Fish are bucketed by their timer, which runs from cycle + delay - 1 for a newborn down to 0
+#[derive(Clone)]
+struct FishPopulation {
+    cycle: usize,
+    delay: usize,
+    timers: Vec<u64>
 }
 
-fn calculate_fish_for(days:u32) -> u64 {
-   let input = fs::read_to_string("input/day06.txt").expect("Failed to read file"); 
+impl FishPopulation {
+    fn new(cycle: usize, delay: usize, fish: &[usize]) -> Self {
+        assert!(cycle > 0, "Fish must take at least a day to spawn");
+
+        let mut timers = vec![0; cycle + delay];
+        for &timer in fish {
+            *timers.get_mut(timer).expect("Fish timer is past the newborn timer") += 1;
+        }
+
+        FishPopulation { cycle, delay, timers }
+    }
+
+    fn load(path: &str, cycle: usize, delay: usize) -> Self {
+        let input = fs::read_to_string(path).expect("Failed to read file");
+
+        let fish = input.trim().split(',').map(|x| x.parse::<usize>().expect("Failed to parse number")).collect::<Vec<usize>>();
+
+        FishPopulation::new(cycle, delay, &fish)
+    }
+
+    // None if a bucket overflows, leaving the population as it was
+    fn step(&mut self) -> Option<()> {
+        let spawning = self.timers[0];
+        let restarted = self.timers[self.cycle % self.timers.len()].checked_add(spawning)?;
+
+        // The spawning fish become the newborns in the last bucket, and start their cycle again as well
+        self.timers.rotate_left(1);
+        self.timers[self.cycle - 1] = restarted;
+
+        Some(())
+    }
+
+    // A u128 holds the sum of any number of u64 buckets that could fit in memory
+    fn total(&self) -> u128 {
+        self.timers.iter().map(|&n| n as u128).sum()
+    }
+
+    // Population on each day from today up to and including the given day, or None if a bucket overflows
+    fn series(&self, days: usize) -> Option<Vec<FishPopulation>> {
+        let mut population = self.clone();
+        let mut series = vec![population.clone()];
 
-   let input_numbers = input.split(',').map(|x| x.parse::<usize>().expect("Failed to parse number")).collect::<Vec<usize>>();
+        for _ in 0..days {
+            population.step()?;
+            series.push(population.clone());
+        }
 
-   let mut fish_numbers:[u64; 9] = [0; 9];
+        Some(series)
+    }
 
-   for num in input_numbers {
-       fish_numbers[num] += 1;
-   }
+    // Column t holds where fish with timer t are a day later
+    fn transition_matrix(&self) -> Matrix {
+        let n = self.cycle + self.delay;
+        let mut m = Matrix::zero(n);
 
-   for _ in 0..days {
-       fish_numbers.rotate_left(1);
-       fish_numbers[6] += fish_numbers[8].clone();
-   }
+        m.cells[(n - 1) * n] += 1;
+        m.cells[(self.cycle - 1) * n] += 1;
+        for t in 1..n {
+            m.cells[(t - 1) * n + t] += 1;
+        }
 
-   fish_numbers.iter().sum::<u64>()
-}
\ No newline at end of file
+        m
+    }
+
+    // Total after any number of days, or None if that overflows when no modulus is given
+    fn project(&self, days: u64, modulus: Option<u128>) -> Option<u128> {
+        let timers: Vec<u128> = self.timers.iter().map(|&n| n as u128).collect();
+
+        self.transition_matrix()
+            .pow(days, modulus)?
+            .apply(&timers, modulus)?
+            .iter()
+            .try_fold(0, |acc, &n| accumulate(acc, n, 1, modulus))
+    }
+
+    // One row per day: the day, the total, then a count per timer
+    fn write_csv(series: &[FishPopulation], out: &mut impl Write) -> io::Result<()> {
+        write!(out, "day,total")?;
+        if let Some(first) = series.first() {
+            for t in 0..first.timers.len() {
+                write!(out, ",timer{}", t)?;
+            }
+        }
+        writeln!(out)?;
+
+        for (day, population) in series.iter().enumerate() {
+            write!(out, "{},{}", day, population.total())?;
+            for n in &population.timers {
+                write!(out, ",{}", n)?;
+            }
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn run() {
+    let population = FishPopulation::load("input/day06.txt", SPAWN_CYCLE, NEWBORN_DELAY);
+
+    let series = population.series(256).expect("Fish count overflowed");
+    println!("After 80 days, there are {} fish", series[80].total());
+    println!("After 256 days, there are {} fish", series[256].total());
+
+    assert_eq!(population.project(256, None), Some(series[256].total()));
+
+    if env::args().nth(2).as_deref() == Some("csv") {
+        fs::create_dir_all("output").expect("Failed to create output directory");
+        let mut out = BufWriter::new(File::create(SERIES_PATH).expect("Failed to create series"));
+        FishPopulation::write_csv(&series, &mut out).and_then(|_| out.flush()).expect("Failed to write series");
+        println!("Wrote {} days to {}", series.len(), SERIES_PATH);
+    }
+
+    // Exact projections only fit for so long, so find the last day they do, if it's within reach
+    let (mut lo, mut hi) = (256, 1 << 16);
+    if population.project(hi, None).is_some() {
+        println!("Exact projections still fit in a u128 on day {}", hi);
+    } else {
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if population.project(mid, None).is_some() {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        println!("Exact projections fit in a u128 until day {}, when there are {} fish", lo, population.project(lo, None).expect("Fish count overflowed"));
+    }
+
+    let days = 1_000_000_000_000;
+    let far = population.project(days, Some(FAR_MODULUS)).expect("Modular counts cannot overflow");
+    println!("After {} days, there are {} fish modulo {}", days, far, FAR_MODULUS);
+}
@@ -1,59 +1,116 @@
 use std::fs;
 
+#[derive(Debug, PartialEq, Eq)]
+struct Alignment {
+    position: i64,
+    fuel: u64
+}
+
+// Fuel for one crab to move a distance
+fn linear_cost(distance: u64) -> u64 {
+    distance
+}
+
+fn triangular_cost(distance: u64) -> u64 {
+    distance * (distance + 1) / 2
+}
+
+fn fuel_at(crabs: &[i64], target: i64, cost: impl Fn(u64) -> u64) -> u64 {
+    crabs.iter().map(|&c| cost((c - target).unsigned_abs())).sum()
+}
+
+// The median minimises the total distance
+fn align_linear(crabs: &[i64]) -> Option<Alignment> {
+    let mut sorted = crabs.to_vec();
+    sorted.sort_unstable();
+
+    let position = *sorted.get(sorted.len() / 2)?;
+    Some(Alignment { position, fuel: fuel_at(crabs, position, linear_cost) })
+}
+
+// The optimum for triangular cost is always within half a step of the mean, so one of the
+// integers either side of it
+fn align_triangular(crabs: &[i64]) -> Option<Alignment> {
+    if crabs.is_empty() {
+        return None;
+    }
+
+    let mean = crabs.iter().sum::<i64>().div_euclid(crabs.len() as i64);
+
+    (mean..=mean + 1)
+        .map(|position| Alignment { position, fuel: fuel_at(crabs, position, triangular_cost) })
+        .min_by_key(|a| a.fuel)
+}
+
+// Works for any cost that is convex in the distance, since the total is then convex in the position
+fn align_convex(crabs: &[i64], cost: impl Fn(u64) -> u64) -> Option<Alignment> {
+    let mut lo = *crabs.iter().min()?;
+    let mut hi = *crabs.iter().max()?;
+
+    while hi - lo > 2 {
+        let m1 = lo + (hi - lo) / 3;
+        let m2 = hi - (hi - lo) / 3;
+
+        let f1 = fuel_at(crabs, m1, &cost);
+        let f2 = fuel_at(crabs, m2, &cost);
+
+        // On a tie a minimum still lies between the two
+        if f1 < f2 {
+            hi = m2 - 1;
+        } else if f1 > f2 {
+            lo = m1 + 1;
+        } else {
+            lo = m1;
+            hi = m2;
+        }
+    }
+
+    (lo..=hi)
+        .map(|position| Alignment { position, fuel: fuel_at(crabs, position, &cost) })
+        .min_by_key(|a| a.fuel)
+}
+
 pub fn run() {
     println!("--Part 1");
     run_part_01();
     println!("--Part 2");
     run_part_02();
+    println!("--Convex search");
+    run_convex();
+}
+
+fn load(path: &str) -> Vec<i64> {
+    let input = fs::read_to_string(path).expect("Failed to read file");
+
+    input.trim().split(',').map(|x| x.parse::<i64>().expect("Failed to parse number")).collect()
 }
 
 fn run_part_01() {
-    let input = fs::read_to_string("input/day07.txt").expect("Failed to read file"); 
- 
-    let input_numbers = input.split(',').map(|x| x.parse::<i32>().expect("Failed to parse number")).collect::<Vec<i32>>();
-
-    let mut lowest_fuel = i32::MAX;
-    
-    let mut current_goal:i32 = 0;
-
-    while current_goal < 10000 {
-        let new_fuel = input_numbers.iter().fold(0, |acc, x| acc + (*x - current_goal).abs());
-        if new_fuel > lowest_fuel {
-            current_goal -= 1;
-            break;
-        }
-        lowest_fuel = new_fuel;
+    let crabs = load("input/day07.txt");
 
-        current_goal += 1;
-    }
+    let best = align_linear(&crabs).expect("There are no crabs");
 
-    println!("At position {}, the crabs will use {} fuel, the lowest", current_goal, lowest_fuel);
+    println!("At position {}, the crabs will use {} fuel, the lowest", best.position, best.fuel);
 }
 
 fn run_part_02() {
-    let input = fs::read_to_string("input/day07.txt").expect("Failed to read file"); 
- 
-    let input_numbers = input.split(',').map(|x| x.parse::<i32>().expect("Failed to parse number")).collect::<Vec<i32>>();
+    let crabs = load("input/day07.txt");
+
+    let best = align_triangular(&crabs).expect("There are no crabs");
 
-    let mut lowest_fuel = i32::MAX;
-    
-    let mut current_goal:i32 = 0;
+    println!("At position {}, the crabs will actually use {} fuel, the lowest", best.position, best.fuel);
+}
 
-    while current_goal < 10000 {
-        let new_fuel = input_numbers.iter().fold(0, |acc, x| acc + tri_num((*x - current_goal).abs()));
+fn run_convex() {
+    let crabs = load("input/day07.txt");
 
-        if new_fuel > lowest_fuel {
-            current_goal -= 1;
-            break;
-        }
-        lowest_fuel = new_fuel;
+    let linear = align_convex(&crabs, linear_cost).expect("There are no crabs");
+    let triangular = align_convex(&crabs, triangular_cost).expect("There are no crabs");
+    let squared = align_convex(&crabs, |d| d * d).expect("There are no crabs");
 
-        current_goal += 1;
-    }
+    assert_eq!(Some(linear.fuel), align_linear(&crabs).map(|a| a.fuel));
+    assert_eq!(Some(triangular.fuel), align_triangular(&crabs).map(|a| a.fuel));
 
-    println!("At position {}, the crabs will actually use {} fuel, the lowest", current_goal, lowest_fuel);
+    println!("Searching agrees: {} fuel for linear cost and {} for triangular cost", linear.fuel, triangular.fuel);
+    println!("With squared cost, position {} is best using {} fuel", squared.position, squared.fuel);
 }
-
-fn tri_num(n:i32) -> i32 {
-    (n * (n + 1)) / 2
-}
\ No newline at end of file
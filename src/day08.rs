@@ -1,58 +1,188 @@
-use std::collections::{HashSet, HashMap};
+use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, BufRead};
 
+const NUM_WIRES: usize = 7;
+const NUM_PATTERNS: usize = 10;
+const NUM_OUTPUTS: usize = 4;
+const UNASSIGNED: u8 = u8::MAX;
+
+// Segments lit for each digit on a correctly wired display, bit 0 being segment a
+const CANONICAL: [Digit; 10] = [
+    Digit { segments: 0b1110111 },
+    Digit { segments: 0b0100100 },
+    Digit { segments: 0b1011101 },
+    Digit { segments: 0b1101101 },
+    Digit { segments: 0b0101110 },
+    Digit { segments: 0b1101011 },
+    Digit { segments: 0b1111011 },
+    Digit { segments: 0b0100101 },
+    Digit { segments: 0b1111111 },
+    Digit { segments: 0b1101111 },
+];
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
 struct Digit {
-    num: Option<u32>,
-    characters: HashSet<char>,
+    segments: u8,
 }
 
 impl Digit {
-    fn new(seq: &str) -> Self {
-        let mut digit = Digit {
-            num: match seq.len() {
-                2 => Option::Some(1),
-                3 => Option::Some(7),
-                4 => Option::Some(4),
-                7 => Option::Some(8),
-                _ => Option::None,
-            },
-            characters: HashSet::new(),
-        };
-
-        for c in seq.chars() {
-            digit.characters.insert(c);
+    // None if any letter is past the last wire
+    fn new(seq: &str) -> Option<Self> {
+        let mut segments = 0;
+        for c in seq.bytes() {
+            let wire = c.checked_sub(b'a').filter(|&w| (w as usize) < NUM_WIRES)?;
+            segments |= 1 << wire;
         }
-        
-        digit
+
+        Some(Digit { segments })
     }
 
     fn contains(&self, other: &Digit) -> bool {
-        self.characters.is_superset(&other.characters)
+        self.segments & other.segments == other.segments
     }
 
-    fn contains_some(&self, other: &Digit) -> u32 {
-        other.characters.iter().fold(0, |acc, x| if self.characters.contains(x) { acc + 1 } else { acc })
+    fn get_number_of_chars(&self) -> u32 {
+        self.segments.count_ones()
     }
+}
 
-    fn get_number_of_chars(&self) -> usize {
-        self.characters.len()
-    }
+// Which segment each wire actually drives, as a bit index
+#[derive(Clone, Copy)]
+struct Wiring {
+    segments: [u8; NUM_WIRES],
+}
 
-    fn assign_num(&mut self, known: &Vec<&Digit>) {
-        for d in known {
-            if self.characters == d.characters {
-                self.num = d.num;
-                break;
+impl Wiring {
+    // Only maps the wires assigned so far
+    fn map(&self, digit: &Digit) -> Digit {
+        let mut mapped = 0;
+        for (wire, &segment) in self.segments.iter().enumerate() {
+            if digit.segments & (1 << wire) != 0 && segment != UNASSIGNED {
+                mapped |= 1 << segment;
             }
         }
+
+        Digit { segments: mapped }
+    }
+
+    fn decode(&self, digit: &Digit) -> Option<u32> {
+        let mapped = self.map(digit);
+        CANONICAL.iter().position(|d| *d == mapped).map(|n| n as u32)
+    }
+
+    fn describe(&self) -> String {
+        self.segments.iter().enumerate()
+            .map(|(wire, &segment)| format!("{}->{}", (b'a' + wire as u8) as char, (b'a' + segment) as char))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+enum Solution {
+    Unique(Wiring),
+    // How many wirings fit the patterns
+    Ambiguous(usize),
+    Inconsistent,
+}
+
+struct Search<'a> {
+    patterns: &'a [Digit],
+    wiring: Wiring,
+    used: u8,
+    num_found: usize,
+    first: Option<Wiring>,
+}
+
+// Assigns wires to segments one at a time, backing out as soon as some pattern can no longer become a digit
+fn solve(patterns: &[Digit]) -> Solution {
+    let mut search = Search {
+        patterns,
+        wiring: Wiring { segments: [UNASSIGNED; NUM_WIRES] },
+        used: 0,
+        num_found: 0,
+        first: None,
+    };
+
+    assign(0, &mut search);
+
+    match (search.num_found, search.first) {
+        (1, Some(w)) => Solution::Unique(w),
+        (0, _) => Solution::Inconsistent,
+        (n, _) => Solution::Ambiguous(n),
+    }
+}
+
+fn assign(wire: usize, search: &mut Search) {
+    if wire == NUM_WIRES {
+        if search.patterns.iter().all(|p| search.wiring.decode(p).is_some()) {
+            search.num_found += 1;
+            search.first.get_or_insert(search.wiring);
+        }
+        return;
     }
 
-    fn set_num(&mut self, num: u32) {
-        self.num = Some(num);
+    for segment in 0..NUM_WIRES as u8 {
+        if search.used & (1 << segment) != 0 {
+            continue;
+        }
+
+        search.wiring.segments[wire] = segment;
+
+        let feasible = search.patterns.iter().all(|p| {
+            let partial = search.wiring.map(p);
+            CANONICAL.iter().any(|d| d.get_number_of_chars() == p.get_number_of_chars() && d.contains(&partial))
+        });
+        if feasible {
+            search.used |= 1 << segment;
+            assign(wire + 1, search);
+            search.used &= !(1 << segment);
+        }
+
+        search.wiring.segments[wire] = UNASSIGNED;
     }
 }
 
+#[derive(Debug)]
+enum EntryError {
+    NoSeparator,
+    WordCount { patterns: usize, outputs: usize },
+    UnknownWire,
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntryError::NoSeparator => write!(f, "has no `|` between patterns and outputs"),
+            EntryError::WordCount { patterns, outputs } => write!(f, "has {} patterns and {} outputs, expected {} and {}", patterns, outputs, NUM_PATTERNS, NUM_OUTPUTS),
+            EntryError::UnknownWire => write!(f, "uses a wire past g"),
+        }
+    }
+}
+
+// Splits an entry into its signal patterns and its outputs, checking each side has the right number of words
+fn split_entry(line: &str) -> Result<(&str, &str), EntryError> {
+    let (patterns, outputs) = line.split_once('|').ok_or(EntryError::NoSeparator)?;
+
+    let num_patterns = patterns.split_whitespace().count();
+    let num_outputs = outputs.split_whitespace().count();
+    if num_patterns != NUM_PATTERNS || num_outputs != NUM_OUTPUTS {
+        return Err(EntryError::WordCount { patterns: num_patterns, outputs: num_outputs });
+    }
+
+    Ok((patterns, outputs))
+}
+
+// Patterns then outputs in one buffer on the stack, so decoding a log never allocates per line
+fn parse_entry(patterns: &str, outputs: &str) -> Result<[Digit; NUM_PATTERNS + NUM_OUTPUTS], EntryError> {
+    let mut digits = [Digit::default(); NUM_PATTERNS + NUM_OUTPUTS];
+    for (slot, word) in digits.iter_mut().zip(patterns.split_whitespace().chain(outputs.split_whitespace())) {
+        *slot = Digit::new(word).ok_or(EntryError::UnknownWire)?;
+    }
+
+    Ok(digits)
+}
+
 pub fn run() {
     println!("--Part 1");
     run_part_01();
@@ -66,10 +196,16 @@ fn run_part_01() {
 
     let mut num_chars: u32 = 0;
 
-    for line in reader.lines() {
+    for (i, line) in reader.lines().enumerate() {
         let l = line.expect("Failed to read line");
-        let split = l.split('|').collect::<Vec<&str>>()[1].split_whitespace().collect::<Vec<&str>>();
-        for digit in split.iter() {
+        let outputs = match split_entry(&l) {
+            Ok((_, outputs)) => outputs,
+            Err(e) => {
+                println!("Line {} {}, skipping it", i + 1, e);
+                continue;
+            }
+        };
+        for digit in outputs.split_whitespace() {
             let num_char = digit.len();
             match num_char {
                 2 => num_chars += 1,
@@ -89,72 +225,44 @@ fn run_part_02() {
     let reader = BufReader::new(file);
 
     let mut sum = 0;
+    let mut first_wiring: Option<Wiring> = None;
 
-    for line in reader.lines() {
-        let mut unknown_fives:Vec<Digit> = Vec::new();
-        let mut unknown_sixes:Vec<Digit> = Vec::new();
-
-        let mut digit_map:HashMap<u32, Digit> = HashMap::new();
-
+    for (i, line) in reader.lines().enumerate() {
         let l = line.expect("Failed to read line");
-        
-        let split = l.split('|').collect::<Vec<&str>>();
-
-        let first_part = split[0].split_whitespace().collect::<Vec<&str>>();
-        let second_part = split[1].split_whitespace().collect::<Vec<&str>>();
-        for digit_str in first_part.iter() {
-            let new_digit = Digit::new(digit_str.clone());
-            match new_digit.num {
-                None => {
-                    match new_digit.get_number_of_chars() {
-                        5 => unknown_fives.push(new_digit),
-                        6 => unknown_sixes.push(new_digit),
-                        _ => (),
-                    }
-                },
-                Some(x) => {
-                    digit_map.insert(x, new_digit);
-                    ()
-                },
-            }
-        }
 
-        for mut digit in unknown_fives.into_iter() {
-            if digit.contains(digit_map.get(&1).expect("Could not find one")) {
-                digit.set_num(3);
-            } else if digit.contains_some(digit_map.get(&4).expect("Could not find four")) == 2 {
-                digit.set_num(2);
-            } else {
-                digit.set_num(5);
+        // The four output digits constrain the wiring just as much as the ten signal patterns do
+        let patterns = match split_entry(&l).and_then(|(patterns, outputs)| parse_entry(patterns, outputs)) {
+            Ok(p) => p,
+            Err(e) => {
+                println!("Line {} {}, skipping it", i + 1, e);
+                continue;
             }
-            digit_map.insert(digit.num.unwrap(), digit);
-        }
+        };
 
-        for mut digit in unknown_sixes.into_iter() {
-            if digit.contains(digit_map.get(&4).expect("Could not find four")) {
-                digit.set_num(9);
-            } else if digit.contains(digit_map.get(&1).expect("Could not find one")) {
-                digit.set_num(0);
-            } else {
-                digit.set_num(6);
+        let wiring = match solve(&patterns) {
+            Solution::Unique(w) => w,
+            Solution::Ambiguous(n) => {
+                println!("Line {} fits {} different wirings, skipping it", i + 1, n);
+                continue;
+            },
+            Solution::Inconsistent => {
+                println!("Line {} fits no wiring, skipping it", i + 1);
+                continue;
             }
-            digit_map.insert(digit.num.unwrap(), digit);
-        }
-
-        let digit_list:Vec<&Digit> = digit_map.values().collect();
+        };
 
         let mut output_num = 0;
-        let mut place = 1000;
-
-        for digit_str in second_part.iter() {            
-            let mut new_digit = Digit::new(digit_str.clone());
-            new_digit.assign_num(&digit_list);
-            output_num += new_digit.num.unwrap() * place;
-            place /= 10;
+        for digit in &patterns[NUM_PATTERNS..] {
+            output_num = output_num * 10 + wiring.decode(digit).expect("Solved wiring failed to decode an output");
         }
 
         sum += output_num;
+        first_wiring.get_or_insert(wiring);
     }
 
     println!("The sum of all outputs is {}", sum);
-}
\ No newline at end of file
+
+    if let Some(wiring) = first_wiring {
+        println!("The first display is wired {}", wiring.describe());
+    }
+}
@@ -1,4 +1,5 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::env;
 use std::fs::File;
 use std::io::{BufReader, BufRead};
 
@@ -8,6 +9,71 @@ struct VentHeightmap {
     height: usize,
 }
 
+struct Basin {
+    low_point: (usize, usize),
+    size: u32,
+    // Corners of the bounding box, inclusive
+    min: (usize, usize),
+    max: (usize, usize),
+}
+
+struct BasinMap {
+    labels: Vec<Option<usize>>,
+    basins: Vec<Basin>,
+    width: usize,
+}
+
+impl BasinMap {
+    // Each basin in one of six colours, with the 9s between them left dark
+    fn render(&self) -> String {
+        let mut s = String::new();
+
+        for row in self.labels.chunks(self.width) {
+            for label in row {
+                match label {
+                    Some(id) => s.push_str(&format!("\x1b[{}m#", 31 + id % 6)),
+                    None => s.push_str("\x1b[90m."),
+                }
+            }
+            s.push_str("\x1b[0m\n");
+        }
+
+        s
+    }
+}
+
+struct DisjointSet {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(n: usize) -> Self {
+        DisjointSet { parent: (0..n).collect(), size: vec![1; n] }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+}
+
 impl VentHeightmap {
     fn new(lines: Vec<String>) -> Self {
         let w = lines[0].len();
@@ -27,77 +93,100 @@ impl VentHeightmap {
         vh
     }
 
-    fn get_height(&self, x:usize, y:usize) -> Option<&u32> {
-        if x > self.width || y > self.height {
+    fn get_height(&self, x:usize, y:usize) -> Option<u32> {
+        if x >= self.width || y >= self.height {
             Option::None
         } else {
-            self.heightmap.get(x + y * self.width)
+            Option::Some(self.heightmap[x + y * self.width])
         }
     }
 
-    fn is_lowest(&self, x:usize, y:usize) -> Option<bool> {
-        if x > self.width || y > self.height {
-            Option::None
-        } else {
-            let center_cell = self.get_height(x, y).unwrap();
-            
-            if x != 0 && self.get_height(x - 1, y).unwrap() <= center_cell {
-                return Option::Some(false)
-            }
+    // The orthogonal neighbours that are on the map, so never wrapping onto the next or previous row
+    fn neighbors(&self, x:usize, y:usize) -> impl Iterator<Item = (usize, usize)> {
+        let (width, height) = (self.width, self.height);
 
-            if y != 0 && self.get_height(x, y - 1).unwrap() <= center_cell {
-                return Option::Some(false)
-            }
+        vec![
+            x.checked_sub(1).map(|l| (l, y)),
+            y.checked_sub(1).map(|u| (x, u)),
+            Some((x + 1, y)),
+            Some((x, y + 1)),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(move |&(nx, ny)| nx < width && ny < height)
+    }
 
-            if x != self.width - 1 && self.get_height(x + 1, y).unwrap() <= center_cell {
-                return Option::Some(false)
-            }
-            
-            if y != self.height - 1 && self.get_height(x, y + 1).unwrap() <= center_cell {
-                return Option::Some(false)
-            }
+    fn is_lowest(&self, x:usize, y:usize) -> Option<bool> {
+        let center_cell = self.get_height(x, y)?;
 
-            Option::Some(true)
-        }
+        Option::Some(self.neighbors(x, y).all(|(nx, ny)| !matches!(self.get_height(nx, ny), Some(h) if h <= center_cell)))
     }
 
-    fn basin_size(&self, x:usize, y:usize) -> Option<u32> {
-        if x > self.width || y > self.height {
-            return Option::None
-        }
-        
-        let mut inside:HashSet<(usize, usize)> = HashSet::new();
-        let mut q:Vec<(usize, usize)> = Vec::new();
-        q.push((x, y));
-
-        while !q.is_empty() {
-            let n = q.swap_remove(0);
-            if !inside.contains(&n) && *self.get_height(n.0, n.1).unwrap() != 9 {
-                inside.insert(n);
-                if n.0 > 0 {
-                    q.push((n.0 - 1, n.1));
+    fn low_points(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.height)
+            .flat_map(move |y| (0..self.width).map(move |x| (x, y)))
+            .filter(move |&(x, y)| self.is_lowest(x, y) == Some(true))
+    }
+
+    // Every cell that isn't a 9 gets the id of its basin, found by joining each such cell with its right and lower
+    // neighbours
+    fn label_basins(&self) -> BasinMap {
+        let index = |x: usize, y: usize| x + y * self.width;
+        let in_basin = |x: usize, y: usize| matches!(self.get_height(x, y), Some(h) if h != 9);
+
+        let mut sets = DisjointSet::new(self.heightmap.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !in_basin(x, y) {
+                    continue;
                 }
-                if n.0 < self.width - 1 {
-                    q.push((n.0 + 1, n.1));
+                if in_basin(x + 1, y) {
+                    sets.union(index(x, y), index(x + 1, y));
                 }
-                if n.1 > 0 {
-                    q.push((n.0, n.1 - 1));
+                if in_basin(x, y + 1) {
+                    sets.union(index(x, y), index(x, y + 1));
                 }
-                if n.1 < self.height - 1 {
-                    q.push((n.0, n.1 + 1));
+            }
+        }
+
+        let mut ids: HashMap<usize, usize> = HashMap::new();
+        let mut labels: Vec<Option<usize>> = vec![None; self.heightmap.len()];
+        let mut basins: Vec<Basin> = Vec::new();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !in_basin(x, y) {
+                    continue;
+                }
+
+                let root = sets.find(index(x, y));
+                let id = *ids.entry(root).or_insert_with(|| {
+                    basins.push(Basin { low_point: (x, y), size: 0, min: (x, y), max: (x, y) });
+                    basins.len() - 1
+                });
+                labels[index(x, y)] = Some(id);
+
+                let basin = &mut basins[id];
+                basin.size += 1;
+                basin.min = (basin.min.0.min(x), basin.min.1.min(y));
+                basin.max = (basin.max.0.max(x), basin.max.1.max(y));
+                if self.get_height(x, y) < self.get_height(basin.low_point.0, basin.low_point.1) {
+                    basin.low_point = (x, y);
                 }
             }
         }
 
-        Option::Some(inside.len() as u32)
+        BasinMap { labels, basins, width: self.width }
     }
 
     fn _show(&self) {
         for y in 0..self.height {
             for x in 0..self.width {
-                print!("{}", self.get_height(x, y).unwrap());
+                if let Some(h) = self.get_height(x, y) {
+                    print!("{}", h);
+                }
             }
-            println!("");
+            println!();
         }
     }
 }
@@ -118,12 +207,8 @@ fn run_part_01() {
 
     let mut total_risk = 0;
 
-    for y in 0..vent_map.height {
-        for x in 0..vent_map.width {
-            if vent_map.is_lowest(x, y).unwrap() {
-                total_risk += vent_map.get_height(x, y).unwrap() + 1;
-            }
-        }
+    for (x, y) in vent_map.low_points() {
+        total_risk += vent_map.get_height(x, y).map_or(0, |h| h + 1);
     }
 
     println!("The total risk level is {}", total_risk);
@@ -136,20 +221,78 @@ fn run_part_02() {
 
     let vent_map = VentHeightmap::new(lines);
 
-    let mut three_largest = vec![0, 0, 0];
+    let basin_map = vent_map.label_basins();
 
-    for y in 0..vent_map.height {
-        for x in 0..vent_map.width {
-            if vent_map.is_lowest(x, y).unwrap() {
-                let basin_size = vent_map.basin_size(x, y).unwrap();
+    let mut sizes: Vec<u32> = basin_map.basins.iter().map(|b| b.size).collect();
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
 
-                if basin_size > three_largest[0] {
-                    three_largest[0] = basin_size;
-                    three_largest.sort();
-                }
-            }
+    println!("The product of the three largest basins is {}", sizes.iter().take(3).product::<u32>());
+
+    if let Some(widest) = basin_map.basins.iter().max_by_key(|b| b.max.0 - b.min.0) {
+        println!("There are {} basins, the widest runs from {:?} to {:?} around the low point {:?}",
+            basin_map.basins.len(), widest.min, widest.max, widest.low_point);
+    }
+
+    if env::args().nth(2).as_deref() == Some("render") {
+        print!("{}", basin_map.render());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 4 wide, 3 high, with a low point in each corner
+    fn grid() -> VentHeightmap {
+        VentHeightmap::new(vec!["1892".to_owned(), "9999".to_owned(), "3894".to_owned()])
+    }
+
+    #[test]
+    fn heights_off_the_map_are_none() {
+        let map = grid();
+
+        assert_eq!(map.get_height(3, 2), Some(4));
+        assert_eq!(map.get_height(map.width, 0), None);
+        assert_eq!(map.get_height(map.width, 1), None);
+        assert_eq!(map.get_height(0, map.height), None);
+        assert_eq!(map.get_height(2, map.height), None);
+    }
+
+    #[test]
+    fn corners_have_two_neighbors_and_edges_three() {
+        let map = grid();
+        let (w, h) = (map.width - 1, map.height - 1);
+
+        for &(x, y) in &[(0, 0), (w, 0), (0, h), (w, h)] {
+            assert_eq!(map.neighbors(x, y).count(), 2, "corner ({}, {})", x, y);
+        }
+        for &(x, y) in &[(1, 0), (2, h), (0, 1), (w, 1)] {
+            assert_eq!(map.neighbors(x, y).count(), 3, "edge ({}, {})", x, y);
         }
+        assert_eq!(map.neighbors(1, 1).count(), 4);
     }
 
-    println!("The product of the three largest basins is {}", three_largest.iter().product::<u32>());
-}
\ No newline at end of file
+    #[test]
+    fn neighbors_do_not_wrap_rows() {
+        let map = grid();
+
+        for y in 0..map.height {
+            assert!(map.neighbors(map.width - 1, y).all(|n| n != (0, y + 1)));
+            assert!(map.neighbors(0, y).all(|n| y == 0 || n != (map.width - 1, y - 1)));
+        }
+    }
+
+    #[test]
+    fn corners_can_be_lowest() {
+        let map = grid();
+        let (w, h) = (map.width - 1, map.height - 1);
+
+        assert_eq!(map.is_lowest(0, 0), Some(true));
+        assert_eq!(map.is_lowest(w, 0), Some(true));
+        assert_eq!(map.is_lowest(0, h), Some(true));
+        assert_eq!(map.is_lowest(w, h), Some(true));
+        assert_eq!(map.is_lowest(1, 0), Some(false));
+        assert_eq!(map.is_lowest(map.width, 0), None);
+        assert_eq!(map.low_points().count(), 4);
+    }
+}
@@ -1,48 +1,124 @@
+use std::env;
 use std::fs::File;
 use std::io::{BufReader, BufRead};
 
+#[derive(Debug, PartialEq, Eq)]
+enum LineStatus {
+    Valid,
+    // expected is None when a closer turns up with nothing open
+    Corrupted { position: usize, expected: Option<char>, found: char },
+    Incomplete { completion: String },
+}
+
+struct BracketPair {
+    open: char,
+    close: char,
+    corrupted_score: u64,
+    completion_score: u64,
+}
+
+// Matches brackets from a configurable set of pairs. Characters that aren't in any pair are skipped
+struct BracketChecker {
+    pairs: Vec<BracketPair>,
+}
+
+impl BracketChecker {
+    fn navigation() -> Self {
+        BracketChecker {
+            pairs: vec![
+                BracketPair { open: '(', close: ')', corrupted_score: 3, completion_score: 1 },
+                BracketPair { open: '[', close: ']', corrupted_score: 57, completion_score: 2 },
+                BracketPair { open: '{', close: '}', corrupted_score: 1197, completion_score: 3 },
+                BracketPair { open: '<', close: '>', corrupted_score: 25137, completion_score: 4 },
+            ],
+        }
+    }
+
+    fn check_line(&self, line: &str) -> LineStatus {
+        // Indices into pairs of every bracket still open
+        let mut stack: Vec<usize> = Vec::new();
+
+        for (position, c) in line.chars().enumerate() {
+            if let Some(opened) = self.pairs.iter().position(|p| p.open == c) {
+                stack.push(opened);
+                continue;
+            }
+
+            if !self.pairs.iter().any(|p| p.close == c) {
+                continue;
+            }
+
+            match stack.pop() {
+                Some(opened) if self.pairs[opened].close == c => (),
+                opened => {
+                    return LineStatus::Corrupted {
+                        position,
+                        expected: opened.map(|o| self.pairs[o].close),
+                        found: c,
+                    }
+                }
+            }
+        }
+
+        if stack.is_empty() {
+            LineStatus::Valid
+        } else {
+            LineStatus::Incomplete { completion: stack.iter().rev().map(|&o| self.pairs[o].close).collect() }
+        }
+    }
+
+    fn corrupted_score(&self, found: char) -> u64 {
+        self.pairs.iter().find(|p| p.close == found).map_or(0, |p| p.corrupted_score)
+    }
+
+    fn completion_score(&self, completion: &str) -> u64 {
+        let mut score = 0;
+        for c in completion.chars() {
+            score *= 5;
+            score += self.pairs.iter().find(|p| p.close == c).map_or(0, |p| p.completion_score);
+        }
+        score
+    }
+}
+
+// A caret under the offending character of a corrupted line, or the missing closers of an incomplete one
+fn diagnostic(line: &str, status: &LineStatus) -> Option<String> {
+    match status {
+        LineStatus::Valid => None,
+        LineStatus::Corrupted { position, expected, found } => {
+            let message = match expected {
+                Some(e) => format!("expected `{}`, found `{}`", e, found),
+                None => format!("found `{}` with nothing open", found),
+            };
+            Some(format!("{}\n{}^ {}", line, " ".repeat(*position), message))
+        },
+        LineStatus::Incomplete { completion } => Some(format!("{}\nincomplete, complete with `{}`", line, completion)),
+    }
+}
+
 pub fn run() {
     println!("--Part 1");
     run_part_01();
     println!("--Part 2");
     run_part_02();
+
+    if env::args().nth(2).as_deref() == Some("report") {
+        println!("--Report");
+        run_report();
+    }
 }
 
 fn run_part_01() {
     let file = File::open("input/day10.txt").expect("Failed to open file");
     let reader = BufReader::new(file);
 
+    let checker = BracketChecker::navigation();
+
     let mut score = 0;
 
     for line in reader.lines() {
-        let l = line.unwrap();
-
-        let mut stack:Vec<char> = Vec::new();
-        for c in l.chars() {
-            match c {
-                '(' | '[' | '{' | '<' => {
-                    stack.push(c);
-                },
-                ')' | ']' | '}' | '>' => {
-                    let expected = stack.pop();
-                    match expected {
-                        Option::None => {
-                            score += score_for_missed(c);
-                            break;
-                        },
-                        Option::Some(x) => {
-                            match c {
-                                ')' => if x != '(' { score += score_for_missed(c) },
-                                ']' => if x != '[' { score += score_for_missed(c) },
-                                '}' => if x != '{' { score += score_for_missed(c) },
-                                '>' => if x != '<' { score += score_for_missed(c) },
-                                _ => ()
-                            }
-                        }
-                    }
-                },
-                _ => ()
-            }
+        if let LineStatus::Corrupted { found, .. } = checker.check_line(&line.unwrap()) {
+            score += checker.corrupted_score(found);
         }
     }
 
@@ -53,86 +129,36 @@ fn run_part_02() {
     let file = File::open("input/day10.txt").expect("Failed to open file");
     let reader = BufReader::new(file);
 
+    let checker = BracketChecker::navigation();
+
     let mut scores:Vec<u64> = Vec::new();
 
     for line in reader.lines() {
-        let l = line.unwrap();
-
-        let mut stack:Vec<char> = Vec::new();
-
-        let mut corrupted = false;
-        for c in l.chars() {
-            match c {
-                '(' | '[' | '{' | '<' => {
-                    stack.push(c);
-                },
-                ')' | ']' | '}' | '>' => {
-                    let expected = stack.pop();
-                    match expected {
-                        Option::None => {
-                            corrupted = true;
-                            break;
-                        },
-                        Option::Some(x) => {
-                            match c {
-                                ')' => if x != '(' { 
-                                    corrupted = true;
-                                    break; 
-                                },
-                                ']' => if x != '[' { 
-                                    corrupted = true;
-                                    break;
-                                 },
-                                '}' => if x != '{' { 
-                                    corrupted = true;
-                                    break;
-                                 },
-                                '>' => if x != '<' { 
-                                    corrupted = true;
-                                    break;
-                                 },
-                                _ => ()
-                            }
-                        }
-                    }
-                },
-                _ => ()
-            }
-        }
-
-        if !corrupted {
-            let mut local_score:u64 = 0;
-            for c in stack.iter().rev() {
-                local_score *= 5;
-                local_score += score_for_inc(*c);
-            }
-            scores.push(local_score);
+        if let LineStatus::Incomplete { completion } = checker.check_line(&line.unwrap()) {
+            scores.push(checker.completion_score(&completion));
         }
     }
 
-    scores.sort();
+    scores.sort_unstable();
 
     let median_score = scores[scores.len() / 2];
 
     println!("The median score for autocomplete is {}", median_score);
 }
 
-fn score_for_missed(x:char) -> u32 {
-    match x {
-        ')' => 3,
-        ']' => 57,
-        '}' => 1197,
-        '>' => 25137,
-        _ => 0
-    }
-}
+fn run_report() {
+    let file = File::open("input/day10.txt").expect("Failed to open file");
+    let reader = BufReader::new(file);
+
+    let checker = BracketChecker::navigation();
+
+    for (i, line) in reader.lines().enumerate() {
+        let l = line.unwrap();
 
-fn score_for_inc(x:char) -> u64 {
-    match x {
-        '(' => 1,
-        '[' => 2,
-        '{' => 3,
-        '<' => 4,
-        _ => 0
+        if let Some(d) = diagnostic(&l, &checker.check_line(&l)) {
+            println!("Line {}:", i + 1);
+            println!("{}", d);
+            println!();
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,18 +1,42 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
+#[derive(Clone, Copy)]
+enum Neighborhood {
+    // The four orthogonal cells
+    VonNeumann,
+    // All eight surrounding cells
+    Moore,
+    // All eight surrounding cells, wrapping around the edges of the grid
+    Toroidal,
+}
+
+// An octopus flashes once its energy goes above threshold, and is set to reset at the end of the step
+#[derive(Clone, Copy)]
+struct JellyRules {
+    neighborhood: Neighborhood,
+    threshold: u32,
+    reset: u32,
+}
+
+impl JellyRules {
+    const DAY_11: JellyRules = JellyRules { neighborhood: Neighborhood::Moore, threshold: 9, reset: 0 };
+}
+
 struct JellyMap {
     jelly_phase: Vec<u32>,
     width:usize,
     height:usize,
+    rules: JellyRules,
 }
 
 impl JellyMap {
-    fn new(lines: Vec<String>) -> Self {
+    fn new(lines: Vec<String>, rules: JellyRules) -> Self {
         let mut map = JellyMap {
             jelly_phase: Vec::new(),
             width: lines[0].len(),
             height: lines.len(),
+            rules,
         };
 
         for line in lines {
@@ -25,58 +49,90 @@ impl JellyMap {
     }
 
     fn step(&mut self) -> u32 {
-        let mut flash:Vec<u32> = vec![0; self.jelly_phase.len()];
+        self.step_waves().iter().flatten().count() as u32
+    }
+
+    // The wave each octopus flashed in this step, if it flashed. Wave 0 is the ones the step's own energy
+    // pushed over, and wave n + 1 is the ones pushed over by wave n
+    fn step_waves(&mut self) -> Vec<Option<u32>> {
+        let mut flash:Vec<Option<u32>> = vec![None; self.jelly_phase.len()];
 
         self.inc_all();
 
-        let mut queue:Vec<(usize, usize)> = Vec::new();
+        let mut wave:Vec<(usize, usize)> = Vec::new();
 
         for (i, x) in self.jelly_phase.iter().enumerate() {
-            if *x > 9 {
-                queue.push((i % self.width, i / self.width));
-                flash[i] = 1;
+            if *x > self.rules.threshold {
+                wave.push((i % self.width, i / self.width));
+                flash[i] = Some(0);
+            }
+        }
+
+        let mut wave_num = 0;
+        while !wave.is_empty() {
+            wave_num += 1;
+
+            let mut next_wave = Vec::new();
+            for next in wave {
+                next_wave.append(&mut self.inc_adj(next, &mut flash, wave_num));
             }
+            wave = next_wave;
         }
 
-        while !queue.is_empty() {
-            let next = queue.pop().unwrap();
-            let mut to_add = self.inc_adj(next, &mut flash);
-            queue.append(&mut to_add);
+        for (i, f) in self.jelly_phase.iter_mut().zip(&flash) {
+            if f.is_some() {
+                *i = self.rules.reset;
+            }
         }
 
-        for i in self.jelly_phase.iter_mut() {
-            if *i > 9 {
-                *i = 0;
+        flash
+    }
+
+    fn inc_adj(&mut self, x: (usize, usize), flash:&mut [Option<u32>], wave_num: u32) -> Vec<(usize, usize)> {
+        let mut over_threshold = Vec::new();
+
+        for (i, j) in self.neighbors(x) {
+            let index = i + j * self.width;
+            self.jelly_phase[index] += 1;
+            if self.jelly_phase[index] > self.rules.threshold && flash[index].is_none() {
+                flash[index] = Some(wave_num);
+                over_threshold.push((i, j));
             }
         }
 
-        flash.iter().sum()
+        over_threshold
     }
 
-    fn inc_adj(&mut self, x: (usize, usize), flash:&mut Vec<u32>) -> Vec<(usize, usize)> {
-        let mut over_nine = Vec::new();
+    fn neighbors(&self, x: (usize, usize)) -> Vec<(usize, usize)> {
+        let (w, h) = (self.width as i64, self.height as i64);
+        let (cx, cy) = (x.0 as i64, x.1 as i64);
 
-        let x_min = if x.0 != 0 { x.0 - 1 } else { x.0 };
-        let y_min = if x.1 != 0 { x.1 - 1 } else { x.1 };
-        let x_max = if x.0 != self.width - 1 { x.0 + 1 } else { x.0 };
-        let y_max = if x.1 != self.height - 1 { x.1 + 1 } else { x.1 };
+        let offsets: &[(i64, i64)] = match self.rules.neighborhood {
+            Neighborhood::VonNeumann => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Neighborhood::Moore | Neighborhood::Toroidal => &[(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)],
+        };
 
-        for j in y_min..=y_max {
-            for i in x_min..=x_max {
-                if i == x.0 && j == x.1 {
-                    continue;
-                }
+        let mut neighbors = Vec::with_capacity(offsets.len());
+        for (dx, dy) in offsets {
+            let (mut i, mut j) = (cx + dx, cy + dy);
 
-                let index = i + j * self.width;
-                self.jelly_phase[index] += 1;
-                if self.jelly_phase[index] > 9 && flash[index] == 0 {
-                    flash[index] = 1;
-                    over_nine.push((i, j));
-                }
+            if let Neighborhood::Toroidal = self.rules.neighborhood {
+                i = i.rem_euclid(w);
+                j = j.rem_euclid(h);
+            }
+
+            // Wrapping a narrow grid can land on the same cell twice, or back on the centre
+            if i < 0 || j < 0 || i >= w || j >= h || (i, j) == (cx, cy) {
+                continue;
+            }
+
+            let n = (i as usize, j as usize);
+            if !neighbors.contains(&n) {
+                neighbors.push(n);
             }
         }
 
-        over_nine
+        neighbors
     }
 
     fn inc_all(&mut self) {
@@ -84,11 +140,83 @@ impl JellyMap {
     }
 }
 
+// Steps a map while keeping the flash waves of every step so far
+struct FlashSimulator {
+    map: JellyMap,
+    history: Vec<Vec<Option<u32>>>,
+}
+
+impl FlashSimulator {
+    fn new(map: JellyMap) -> Self {
+        FlashSimulator { map, history: Vec::new() }
+    }
+
+    fn step(&mut self) -> u32 {
+        let waves = self.map.step_waves();
+        let flashes = waves.iter().flatten().count() as u32;
+        self.history.push(waves);
+        flashes
+    }
+
+    // Step numbers count from 1
+    fn flash_mask(&self, step: usize) -> Vec<bool> {
+        self.history[step - 1].iter().map(|w| w.is_some()).collect()
+    }
+
+    fn is_synchronized(&self, step: usize) -> bool {
+        self.flash_mask(step).iter().all(|&f| f)
+    }
+
+    // Steps on until every octopus flashes together, then until the map is back in that same state.
+    // Gives the synchronized step and the period, or None if that takes more than max_steps in all
+    fn synchronization(&mut self, max_steps: usize) -> Option<(usize, usize)> {
+        let mut first: Option<(usize, Vec<u32>)> = None;
+
+        while self.history.len() < max_steps {
+            self.step();
+
+            let step = self.history.len();
+            if !self.is_synchronized(step) {
+                continue;
+            }
+
+            match &first {
+                None => first = Some((step, self.map.jelly_phase.clone())),
+                Some((first_step, phase)) if *phase == self.map.jelly_phase => return Some((*first_step, step - first_step)),
+                _ => ()
+            }
+        }
+
+        None
+    }
+
+    // Each octopus as the wave it flashed in, '.' if it didn't
+    fn render(&self, step: usize) -> String {
+        let mut s = String::new();
+
+        for row in self.history[step - 1].chunks(self.map.width) {
+            for wave in row {
+                s.push(match wave {
+                    Some(w) => std::char::from_digit(*w, 36).unwrap_or('+'),
+                    None => '.'
+                });
+            }
+            s.push('\n');
+        }
+
+        s
+    }
+}
+
 pub fn run() {
     println!("--Part 1");
     run_part_01();
     println!("--Part 2");
     run_part_02();
+    println!("--Dynamics");
+    run_dynamics();
+    println!("--Variants");
+    run_variants();
 }
 
 fn run_part_01() {
@@ -96,7 +224,7 @@ fn run_part_01() {
     let reader = BufReader::new(file);
     let lines = reader.lines().map(|x| x.expect("Could not read line")).collect::<Vec<String>>();
 
-    let mut jelly_map = JellyMap::new(lines);
+    let mut jelly_map = JellyMap::new(lines, JellyRules::DAY_11);
 
     let mut flashes = 0;
 
@@ -112,7 +240,7 @@ fn run_part_02() {
     let reader = BufReader::new(file);
     let lines = reader.lines().map(|x| x.expect("Could not read line")).collect::<Vec<String>>();
 
-    let mut jelly_map = JellyMap::new(lines);
+    let mut jelly_map = JellyMap::new(lines, JellyRules::DAY_11);
 
     let mut step = 0;
     while jelly_map.step() != jelly_map.jelly_phase.len() as u32 {
@@ -120,4 +248,45 @@ fn run_part_02() {
     }
 
     println!("The first synchronization was at {} steps", step + 1);
-}
\ No newline at end of file
+}
+
+fn run_dynamics() {
+    let file = File::open("input/day11.txt").expect("Failed to open file");
+    let reader = BufReader::new(file);
+    let lines = reader.lines().map(|x| x.expect("Could not read line")).collect::<Vec<String>>();
+
+    let mut simulator = FlashSimulator::new(JellyMap::new(lines, JellyRules::DAY_11));
+
+    match simulator.synchronization(10000) {
+        Some((first, period)) => println!("Synchronized at step {}, then every {} steps", first, period),
+        None => println!("Never synchronized")
+    }
+
+    let deepest = (1..=simulator.history.len())
+        .max_by_key(|&step| simulator.history[step - 1].iter().flatten().max().copied())
+        .expect("No steps were simulated");
+
+    println!("The longest cascade was at step {}:", deepest);
+    print!("{}", simulator.render(deepest));
+}
+
+fn run_variants() {
+    let file = File::open("input/day11.txt").expect("Failed to open file");
+    let reader = BufReader::new(file);
+    let lines = reader.lines().map(|x| x.expect("Could not read line")).collect::<Vec<String>>();
+
+    let variants = [
+        ("von Neumann", JellyRules { neighborhood: Neighborhood::VonNeumann, ..JellyRules::DAY_11 }),
+        ("toroidal", JellyRules { neighborhood: Neighborhood::Toroidal, ..JellyRules::DAY_11 }),
+        ("threshold 7, reset 1", JellyRules { threshold: 7, reset: 1, ..JellyRules::DAY_11 }),
+    ];
+
+    for (name, rules) in variants {
+        let mut simulator = FlashSimulator::new(JellyMap::new(lines.clone(), rules));
+
+        match simulator.synchronization(10000) {
+            Some((first, period)) => println!("The {} variant synchronized at step {}, then every {} steps", name, first, period),
+            None => println!("The {} variant never synchronized in 10000 steps", name)
+        }
+    }
+}
@@ -1,162 +1,308 @@
-use std::fs::File;
+use std::env;
+use std::fs::{self, File};
 use std::io::{BufReader, BufRead};
 use std::collections::HashMap;
 
-#[derive(Clone)]
-struct Path<'a> {
-    visited_small_num:HashMap<&'a str, i32>,
-    visited:Vec<&'a str>,
-    head:&'a str,
-    used_extra:bool,
+const DOT_PATH: &str = "output/day12.dot";
+
+// Caves are interned to indices. Small caves also get a bit each so a set of visited ones fits in a u64
+struct CaveGraph {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+    small_bit: Vec<Option<u32>>,
+    connections: Vec<Vec<usize>>,
+    start: usize,
+    end: usize,
 }
 
-impl<'a> Path<'a> {
-    fn new() -> Self {
-        let mut p = Path{
-            visited_small_num: HashMap::new(),
-            visited: Vec::new(),
-            head: "start",
-            used_extra: false,
+impl CaveGraph {
+    fn load(path: &str) -> Self {
+        let file = File::open(path).expect("Failed to open file");
+        let reader = BufReader::new(file);
+
+        let mut graph = CaveGraph {
+            names: Vec::new(),
+            ids: HashMap::new(),
+            small_bit: Vec::new(),
+            connections: Vec::new(),
+            start: 0,
+            end: 0,
         };
 
-        p.visited.push("start");
-        p.visited_small_num.insert("start", i32::MAX);
+        for line in reader.lines() {
+            let l = line.expect("Failed to parse line");
+            let split = l.split('-').collect::<Vec<&str>>();
 
-        p
-    }
+            assert_eq!(split.len(), 2);
 
-    fn child_to(&self, new_node: &'a str, extra:bool) -> Option<Self> {
-        let mut used_extra = self.used_extra;
-        
-        if new_node == "start" {
-            return None;
+            let a = graph.intern(split[0]);
+            let b = graph.intern(split[1]);
+            graph.connections[a].push(b);
+            graph.connections[b].push(a);
         }
 
-        if is_lowercase(new_node) {
-            match self.visited_small_num.get(new_node) {
-                Some(x) => { 
-                    if *x >= 1 { 
-                        if extra && !used_extra {
-                            used_extra = true;
-                        } else {
-                            return None 
-                        }
-                    } 
-                },
-                None => ()
-            }
+        graph.start = *graph.ids.get("start").expect("There is no start cave");
+        graph.end = *graph.ids.get("end").expect("There is no end cave");
+
+        graph
+    }
+
+    fn intern(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
         }
 
-        let mut x = self.clone();
+        let small_bit = if is_lowercase(name) {
+            let bit = self.small_bit.iter().flatten().count() as u32;
+            assert!(bit < 64, "Too many small caves to track");
+            Some(bit)
+        } else {
+            None
+        };
 
-        *x.visited_small_num.entry(new_node).or_insert(0) += 1;
-        x.visited.push(new_node);
-        x.head = new_node;
-        x.used_extra = used_extra;
+        let id = self.names.len();
+        self.names.push(name.to_owned());
+        self.ids.insert(name.to_owned(), id);
+        self.small_bit.push(small_bit);
+        self.connections.push(Vec::new());
+
+        id
+    }
 
-        Some(x)
+    fn count_paths(&self, policy: &VisitPolicy) -> u64 {
+        let mut memo: HashMap<(usize, u64, u32), u64> = HashMap::new();
+        let visited = self.visit(0, self.start);
+        self.count_from(self.start, visited, 0, policy, &mut memo)
     }
 
-    fn create_path_string(&self) -> String {
-        let mut path_string = String::new();
-        for node in &self.visited {
-            path_string.push_str(node);
-            path_string.push_str(",");
+    // Paths to the end from cave, given the small caves already visited and how many revisits are spent
+    fn count_from(&self, cave: usize, visited: u64, revisits: u32, policy: &VisitPolicy, memo: &mut HashMap<(usize, u64, u32), u64>) -> u64 {
+        if cave == self.end {
+            return 1;
         }
 
-        path_string
+        if let Some(&n) = memo.get(&(cave, visited, revisits)) {
+            return n;
+        }
+
+        let mut num = 0;
+        for &next in &self.connections[cave] {
+            if next == self.start {
+                continue;
+            }
+
+            let revisit = self.is_visited(visited, next);
+            if !policy.allows(self, next, revisit, revisits) {
+                continue;
+            }
+
+            if revisit {
+                num += self.count_from(next, visited, revisits + 1, policy, memo);
+            } else {
+                num += self.count_from(next, self.visit(visited, next), revisits, policy, memo);
+            }
+        }
+
+        memo.insert((cave, visited, revisits), num);
+        num
     }
-}
 
-pub fn run() {
-    println!("--Part 1");
-    run_part_01();
-    println!("--Part 2");
-    run_part_02();
-}
+    fn paths<'a>(&'a self, policy: &'a VisitPolicy) -> Paths<'a> {
+        let mut visits = vec![0; self.names.len()];
+        visits[self.start] = 1;
 
-fn run_part_01() {
-    let file = File::open("input/day12.txt").expect("Failed to open file");
-    let reader = BufReader::new(file);
-
-    let mut map:HashMap<String, Vec<String>> = HashMap::new();
-
-    for line in reader.lines() {
-        let l = line.expect("Failed to parse line");
-        let split = l.split("-").collect::<Vec<&str>>();
-    
-        assert_eq!(split.len(), 2);
-    
-        map.entry(split[0].to_owned()).or_insert(Vec::new()).push(split[1].to_owned());
-        map.entry(split[1].to_owned()).or_insert(Vec::new()).push(split[0].to_owned());
+        Paths {
+            graph: self,
+            policy,
+            stack: vec![Frame { cave: self.start, next: 0, revisit: false }],
+            visits,
+            revisits: 0,
+        }
+    }
+
+    fn visit(&self, visited: u64, cave: usize) -> u64 {
+        match self.small_bit[cave] {
+            Some(bit) => visited | (1 << bit),
+            None => visited,
+        }
     }
 
-    let mut current_paths:Vec<Path> = Vec::new();
+    fn is_visited(&self, visited: u64, cave: usize) -> bool {
+        match self.small_bit[cave] {
+            Some(bit) => visited & (1 << bit) != 0,
+            None => false,
+        }
+    }
 
-    current_paths.push(Path::new());
+    fn path_string(&self, path: &[usize]) -> String {
+        path.iter().map(|&c| self.names[c].as_str()).collect::<Vec<&str>>().join(",")
+    }
 
-    let mut found_paths:Vec<String> = Vec::new();
+    // Graphviz source: small caves as ellipses, big caves as boxes, start and end doubled
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("graph caves {\n");
 
-    while let Some(x) = current_paths.pop() {
-        if let Some(connections) = map.get(x.head) {
-            for node in connections {
-                if let Some(new_path) = x.child_to(node.as_str(), false) {
-                    if new_path.head == "end" {
-                        found_paths.push(x.create_path_string());
-                    } else {
-                        current_paths.push(new_path);
-                    }
+        for (id, name) in self.names.iter().enumerate() {
+            let shape = if id == self.start || id == self.end {
+                "doublecircle"
+            } else if self.small_bit[id].is_some() {
+                "ellipse"
+            } else {
+                "box"
+            };
+            dot.push_str(&format!("    \"{}\" [shape={}];\n", name, shape));
+        }
+
+        for (a, connections) in self.connections.iter().enumerate() {
+            for &b in connections {
+                if a < b {
+                    dot.push_str(&format!("    \"{}\" -- \"{}\";\n", self.names[a], self.names[b]));
                 }
             }
         }
+
+        dot.push_str("}\n");
+        dot
     }
+}
 
-    println!("There were {} paths", found_paths.len());
+// Which caves a path may enter. Start is never re-entered and big caves can always be revisited,
+// whatever the policy
+enum VisitPolicy {
+    // Small caves may be visited again this many times between them
+    Revisits(u32),
+    // Only the named small cave may be visited again, at most this many times
+    NamedRevisits(String, u32),
+    // These caves may not be entered at all, and small caves only once
+    Forbid(Vec<String>),
 }
 
-fn run_part_02() {
-    let file = File::open("input/day12.txt").expect("Failed to open file");
-    let reader = BufReader::new(file);
-
-    let mut map:HashMap<String, Vec<String>> = HashMap::new();
-
-    for line in reader.lines() {
-        let l = line.expect("Failed to parse line");
-        let split = l.split("-").collect::<Vec<&str>>();
-    
-        assert_eq!(split.len(), 2);
-    
-        map.entry(split[0].to_owned()).or_insert(Vec::new()).push(split[1].to_owned());
-        map.entry(split[1].to_owned()).or_insert(Vec::new()).push(split[0].to_owned());
+impl VisitPolicy {
+    fn allows(&self, graph: &CaveGraph, cave: usize, revisit: bool, revisits: u32) -> bool {
+        match self {
+            VisitPolicy::Revisits(limit) => !revisit || revisits < *limit,
+            VisitPolicy::NamedRevisits(name, limit) => !revisit || (graph.names[cave] == *name && revisits < *limit),
+            VisitPolicy::Forbid(names) => !revisit && !names.contains(&graph.names[cave]),
+        }
     }
+}
+
+struct Frame {
+    cave: usize,
+    next: usize,
+    // Whether entering this cave spent a revisit
+    revisit: bool,
+}
+
+// Depth first walk over every path from start to end, one path per item
+struct Paths<'a> {
+    graph: &'a CaveGraph,
+    policy: &'a VisitPolicy,
+    stack: Vec<Frame>,
+    visits: Vec<u32>,
+    revisits: u32,
+}
 
-    let mut current_paths:Vec<Path> = Vec::new();
+impl<'a> Iterator for Paths<'a> {
+    type Item = Vec<usize>;
 
-    current_paths.push(Path::new());
+    fn next(&mut self) -> Option<Vec<usize>> {
+        let graph = self.graph;
 
-    let mut found_paths:Vec<String> = Vec::new();
+        loop {
+            let top = self.stack.last_mut()?;
 
-    while let Some(x) = current_paths.pop() {
-        if let Some(connections) = map.get(x.head) {
-            for node in connections {
-                if let Some(new_path) = x.child_to(node.as_str(), true) {
-                    if new_path.head == "end" {
-                        found_paths.push(x.create_path_string());
-                    } else {
-                        current_paths.push(new_path);
+            let next = match graph.connections[top.cave].get(top.next) {
+                Some(&n) => n,
+                None => {
+                    self.visits[top.cave] -= 1;
+                    if top.revisit {
+                        self.revisits -= 1;
                     }
+                    self.stack.pop();
+                    continue;
                 }
+            };
+            top.next += 1;
+
+            if next == graph.start {
+                continue;
+            }
+
+            // The policy gets a say over end too, as it does when counting
+            let revisit = graph.small_bit[next].is_some() && self.visits[next] > 0;
+            if !self.policy.allows(graph, next, revisit, self.revisits) {
+                continue;
+            }
+
+            if next == graph.end {
+                let mut path: Vec<usize> = self.stack.iter().map(|f| f.cave).collect();
+                path.push(next);
+                return Some(path);
+            }
+
+            if revisit {
+                self.revisits += 1;
             }
+
+            self.visits[next] += 1;
+            self.stack.push(Frame { cave: next, next: 0, revisit });
         }
     }
+}
 
-    println!("There were {} paths for the two-visit solution", found_paths.len());
+pub fn run() {
+    println!("--Part 1");
+    run_part_01();
+    println!("--Part 2");
+    run_part_02();
+    println!("--Variants");
+    run_variants();
 }
 
-fn is_lowercase(s: &str) -> bool {
-    if s.to_lowercase() == s {
-        true
-    } else {
-        false
+fn run_part_01() {
+    let graph = CaveGraph::load("input/day12.txt");
+
+    match env::args().nth(2).as_deref() {
+        Some("paths") => {
+            for path in graph.paths(&VisitPolicy::Revisits(0)) {
+                println!("{}", graph.path_string(&path));
+            }
+        },
+        Some("dot") => {
+            fs::create_dir_all("output").expect("Failed to create output directory");
+            fs::write(DOT_PATH, graph.to_dot()).expect("Failed to write graph");
+            println!("Wrote the cave system to {}", DOT_PATH);
+        },
+        _ => ()
     }
-}
\ No newline at end of file
+
+    println!("There were {} paths", graph.count_paths(&VisitPolicy::Revisits(0)));
+}
+
+fn run_part_02() {
+    let graph = CaveGraph::load("input/day12.txt");
+
+    println!("There were {} paths for the two-visit solution", graph.count_paths(&VisitPolicy::Revisits(1)));
+}
+
+fn run_variants() {
+    let graph = CaveGraph::load("input/day12.txt");
+
+    println!("There were {} paths with two revisits", graph.count_paths(&VisitPolicy::Revisits(2)));
+
+    // Pick the first small cave in the input that isn't start or end
+    let cave = (0..graph.names.len())
+        .find(|&c| graph.small_bit[c].is_some() && c != graph.start && c != graph.end)
+        .map(|c| graph.names[c].clone());
+
+    if let Some(name) = cave {
+        let named = graph.count_paths(&VisitPolicy::NamedRevisits(name.clone(), 2));
+        let forbidden = graph.count_paths(&VisitPolicy::Forbid(vec![name.clone()]));
+        println!("There were {} paths visiting {} up to three times, and {} paths avoiding it", named, name, forbidden);
+    }
+}
+
+fn is_lowercase(s: &str) -> bool {
+    s.to_lowercase() == s
+}
@@ -1,81 +1,143 @@
+use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, BufRead};
 use std::collections::HashSet;
 
-pub fn run() {
-    println!("--Part 1");
-    run_part_01();
-    println!("--Part 2");
-    run_part_02();
+use crate::ocr;
+
+type Dot = (i32, i32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Fold {
+    X(i32),
+    Y(i32)
 }
 
-fn run_part_01() {
-    let file = File::open("input/day13.txt").expect("Failed to open file");
-    let reader = BufReader::new(file);
+impl Fold {
+    fn parse(s: &str) -> Fold {
+        let inst = s.strip_prefix("fold along ").expect("Fold is missing its prefix");
+        let split = inst.split('=').collect::<Vec<&str>>();
 
-    let mut map:HashSet<(i32, i32)> = HashSet::new();
+        assert_eq!(split.len(), 2);
 
-    let mut lines = reader.lines();
+        let num = split[1].parse::<i32>().expect("Cannot parse fold coordinate");
 
-    //Iterate coord
-    while let Some(Ok(s)) = lines.next() {
-        if s.is_empty() {
-            break;
+        match split[0] {
+            "x" => Fold::X(num),
+            "y" => Fold::Y(num),
+            axis => panic!("Unknown fold axis {}", axis)
         }
+    }
 
-        let numbers = s.split(",").map(|x| x.parse::<i32>().expect("Could not parse dot coordinate")).collect::<Vec<i32>>();
-        
-        assert_eq!(numbers.len(), 2);
+    fn apply(&self, dot: Dot) -> Dot {
+        match *self {
+            Fold::X(num) if dot.0 > num => (2 * num - dot.0, dot.1),
+            Fold::Y(num) if dot.1 > num => (dot.0, 2 * num - dot.1),
+            _ => dot
+        }
+    }
+}
 
-        map.insert((numbers[0], numbers[1]));
+#[derive(Debug)]
+enum FoldError {
+    // The line isn't the middle of the sheet, or dots on the far side would hang past the near edge once folded
+    InvalidLine(Fold),
+    DotOnLine(Fold, Dot)
+}
+
+impl fmt::Display for FoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FoldError::InvalidLine(fold) => write!(f, "{:?} does not fall on the middle of the sheet", fold),
+            FoldError::DotOnLine(fold, dot) => write!(f, "{:?} runs through the dot at {:?}", fold, dot)
+        }
     }
+}
 
-    //Iterate folds
-    while let Some(Ok(s)) = lines.next() {
-        let inst = &s[11..];
-        let split = inst.split("=").collect::<Vec<&str>>();
-        
-        assert_eq!(split.len(), 2);
+#[derive(Clone)]
+struct Sheet {
+    dots: HashSet<Dot>,
+    // Only known along an axis once it has been folded, as each fold halves the sheet
+    width: Option<i32>,
+    height: Option<i32>
+}
 
-        let num = split[1].parse::<i32>().expect("Cannot parse fold coordinate");
+// The sheet after every fold so far, so folds can be undone
+struct Paper {
+    history: Vec<Sheet>,
+    folds: Vec<Fold>
+}
 
-        let mut new_map:HashSet<(i32, i32)> = HashSet::new();
+impl Paper {
+    fn new(dots: HashSet<Dot>) -> Self {
+        Paper { history: vec![Sheet { dots, width: None, height: None }], folds: Vec::new() }
+    }
 
-        match split[0] {
-            "x" => {
-                for point in &map {
-                    if point.0 > num {
-                        new_map.insert((2 * num - point.0, point.1));
-                    } else {
-                        new_map.insert((point.0, point.1));
-                    }
-                }
-            },
-            "y" => {
-                for point in &map {
-                    if point.1 > num {
-                        new_map.insert((point.0, 2 * num - point.1));
-                    } else {
-                        new_map.insert((point.0, point.1));
-                    }
-                }
-            },
-            _ => ()
+    fn current(&self) -> &Sheet {
+        self.history.last().expect("Paper has no sheet")
+    }
+
+    fn dots(&self) -> &HashSet<Dot> {
+        &self.current().dots
+    }
+
+    // Each fold has to fall on the middle line of the sheet. Before the first fold along an axis the sheet's size
+    // isn't known, so then it just has to have dots past it, and none past where the near edge would be
+    fn fold(&mut self, fold: Fold) -> Result<(), FoldError> {
+        let sheet = self.current();
+
+        let (num, extent, coord): (i32, Option<i32>, fn(&Dot) -> i32) = match fold {
+            Fold::X(num) => (num, sheet.width, |d| d.0),
+            Fold::Y(num) => (num, sheet.height, |d| d.1)
+        };
+        let on_middle = match extent {
+            Some(e) => e == 2 * num + 1,
+            None => sheet.dots.iter().any(|d| coord(d) > num) && sheet.dots.iter().all(|d| coord(d) <= 2 * num)
+        };
+        if num <= 0 || !on_middle {
+            return Err(FoldError::InvalidLine(fold));
         }
 
-        map = new_map;
+        if let Some(&dot) = sheet.dots.iter().find(|d| coord(d) == num) {
+            return Err(FoldError::DotOnLine(fold, dot));
+        }
+
+        let dots = sheet.dots.iter().map(|d| fold.apply(*d)).collect();
+        let (width, height) = match fold {
+            Fold::X(num) => (Some(num), sheet.height),
+            Fold::Y(num) => (sheet.width, Some(num))
+        };
+
+        self.history.push(Sheet { dots, width, height });
+        self.folds.push(fold);
 
-        break;
+        Ok(())
     }
 
-    println!("There are {} points after one fold", map.len());
+    fn unfold(&mut self) -> Option<Fold> {
+        let fold = self.folds.pop()?;
+        self.history.pop();
+        Some(fold)
+    }
+
+    // Starting with the unfolded paper
+    fn dot_counts(&self) -> Vec<usize> {
+        self.history.iter().map(|s| s.dots.len()).collect()
+    }
 }
 
-fn run_part_02() {
-    let file = File::open("input/day13.txt").expect("Failed to open file");
+pub fn run() {
+    println!("--Part 1");
+    run_part_01();
+    println!("--Part 2");
+    run_part_02();
+}
+
+fn load(path: &str) -> (Paper, Vec<Fold>) {
+    let file = File::open(path).expect("Failed to open file");
     let reader = BufReader::new(file);
 
-    let mut map:HashSet<(i32, i32)> = HashSet::new();
+    let mut map:HashSet<Dot> = HashSet::new();
 
     let mut lines = reader.lines();
 
@@ -85,51 +147,51 @@ fn run_part_02() {
             break;
         }
 
-        let numbers = s.split(",").map(|x| x.parse::<i32>().expect("Could not parse dot coordinate")).collect::<Vec<i32>>();
-        
+        let numbers = s.split(',').map(|x| x.parse::<i32>().expect("Could not parse dot coordinate")).collect::<Vec<i32>>();
+
         assert_eq!(numbers.len(), 2);
 
         map.insert((numbers[0], numbers[1]));
     }
 
     //Iterate folds
-    while let Some(Ok(s)) = lines.next() {
-        let inst = &s[11..];
-        let split = inst.split("=").collect::<Vec<&str>>();
-        
-        assert_eq!(split.len(), 2);
+    let folds = lines.map(|l| Fold::parse(&l.expect("Failed to read line"))).collect();
 
-        let num = split[1].parse::<i32>().expect("Cannot parse fold coordinate");
+    (Paper::new(map), folds)
+}
 
-        let mut new_map:HashSet<(i32, i32)> = HashSet::new();
+fn run_part_01() {
+    let (mut paper, folds) = load("input/day13.txt");
 
-        match split[0] {
-            "x" => {
-                for point in &map {
-                    if point.0 > num {
-                        new_map.insert((2 * num - point.0, point.1));
-                    } else {
-                        new_map.insert((point.0, point.1));
-                    }
-                }
-            },
-            "y" => {
-                for point in &map {
-                    if point.1 > num {
-                        new_map.insert((point.0, 2 * num - point.1));
-                    } else {
-                        new_map.insert((point.0, point.1));
-                    }
-                }
-            },
-            _ => ()
-        }
+    paper.fold(folds[0]).unwrap_or_else(|e| panic!("Invalid fold: {}", e));
+
+    println!("There are {} points after one fold", paper.dots().len());
+}
+
+fn run_part_02() {
+    let (mut paper, folds) = load("input/day13.txt");
 
-        map = new_map;
+    for fold in &folds {
+        paper.fold(*fold).unwrap_or_else(|e| panic!("Invalid fold: {}", e));
     }
 
+    let map = paper.dots().clone();
+
     println!("There are {} points after all folds", map.len());
 
+    let counts: Vec<String> = paper.dot_counts().iter().map(|c| c.to_string()).collect();
+    println!("The dot count after each fold was {}", counts.join(" -> "));
+
+    while paper.unfold().is_some() {}
+    println!("Unfolding all {} folds leaves {} points", folds.len(), paper.dots().len());
+
+    if let Some(code) = ocr::recognize(&map) {
+        println!("The code is {}", code);
+        return;
+    }
+
+    println!("Could not read the code, it looks like this:");
+
     let max_x = map.iter().fold(0, |acc,x| if x.0 > acc { x.0 } else { acc });
     let max_y = map.iter().fold(0, |acc,x| if x.1 > acc { x.1 } else { acc });
 
@@ -140,8 +202,8 @@ fn run_part_02() {
             } else {
                 print!("  ");
             }
-        } 
+        }
 
-        println!("");
+        println!();
     }
-}
\ No newline at end of file
+}
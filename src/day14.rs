@@ -1,137 +1,261 @@
-use std::fs::File;
-use std::io::{BufReader, BufRead};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufRead, BufWriter, Write};
 use std::collections::HashMap;
 
-pub fn run() {
-    println!("--Part 1");
-    run_part_01();
-    println!("--Part 2");
-    run_part_02();
+use crate::matrix::{accumulate, Matrix};
+
+// Counts modulo this are used once exact counts no longer fit
+const FAR_MODULUS: u128 = 1_000_000_007;
+
+const REPORT_PATH: &str = "output/day14_report.csv";
+
+struct StepReport {
+    step: u64,
+    freq_map: HashMap<char, u128>,
+    length: u128,
+    // How many times each rule fired to produce this step, zero for the template itself
+    rule_usage: HashMap<(usize, usize), u128>
 }
 
-fn run_part_01() {
-    let file = File::open("input/day14.txt").expect("Failed to open file");
-    let reader = BufReader::new(file);
+struct PolymerReport {
+    steps: Vec<StepReport>,
+    // Pairs that show up in the polymer but have no rule, so are never split
+    missing_pairs: Vec<(char, char)>
+}
 
-    let mut lines = reader.lines();
+// Elements are numbered in order of first appearance, and a pair (a, b) is tracked as a * elements + b
+struct Polymer {
+    elements: Vec<char>,
+    template: Vec<usize>,
+    pair_ins_map: HashMap<(usize, usize), usize>
+}
 
-    let mut source = lines.next().expect("Failed to parse first line").expect("Failed to parse first line");
-    lines.next(); //Skip empty line
+impl Polymer {
+    fn load(path: &str) -> Self {
+        let file = File::open(path).expect("Failed to open file");
+        let reader = BufReader::new(file);
 
-    let mut pair_ins_map:HashMap<String, String> = HashMap::new();
+        let mut lines = reader.lines();
 
-    for line in lines {
-        let l = line.expect("Failed to parse line");
+        let source = lines.next().expect("Failed to parse first line").expect("Failed to parse first line");
+        lines.next(); //Skip empty line
 
-        let split = l.split(" -> ").collect::<Vec<&str>>();
+        let mut polymer = Polymer { elements: Vec::new(), template: Vec::new(), pair_ins_map: HashMap::new() };
 
-        assert_eq!(split.len(), 2);
+        polymer.template = source.chars().map(|c| polymer.element_id(c)).collect();
 
-        pair_ins_map.insert(split[0].to_owned(), split[1].to_owned());
-    }
-    
-    let iterations = 10 ;
+        for line in lines {
+            let l = line.expect("Failed to parse line");
 
-    for _ in 0..iterations {
-        let mut i = 2;
+            let split = l.split(" -> ").collect::<Vec<&str>>();
 
-        while i <= source.len() {
-            let last_pair = &source[i - 2..i];
+            assert_eq!(split.len(), 2);
 
-            if let Some(x) = pair_ins_map.get(last_pair) {
-                source.insert_str(i - 1, x.as_str());
+            let mut pair = split[0].chars().map(|c| polymer.element_id(c));
+            let a = pair.next().expect("Rule is missing a pair");
+            let b = pair.next().expect("Rule is missing a pair");
+            let ins = polymer.element_id(split[1].chars().next().expect("Rule is missing an insertion"));
+
+            polymer.pair_ins_map.insert((a, b), ins);
+        }
 
-                i += 1;
+        polymer
+    }
+
+    fn element_id(&mut self, c: char) -> usize {
+        match self.elements.iter().position(|&e| e == c) {
+            Some(i) => i,
+            None => {
+                self.elements.push(c);
+                self.elements.len() - 1
             }
+        }
+    }
 
-            i += 1;
+    fn pair_index(&self, a: usize, b: usize) -> usize {
+        a * self.elements.len() + b
+    }
+
+    // Column p holds what pair p turns into after one step
+    fn transition_matrix(&self) -> Matrix {
+        let e = self.elements.len();
+        let mut m = Matrix::zero(e * e);
+
+        for a in 0..e {
+            for b in 0..e {
+                let from = self.pair_index(a, b);
+                match self.pair_ins_map.get(&(a, b)) {
+                    Some(&ins) => {
+                        m.cells[self.pair_index(a, ins) * m.n + from] += 1;
+                        m.cells[self.pair_index(ins, b) * m.n + from] += 1;
+                    },
+                    None => m.cells[from * m.n + from] += 1
+                }
+            }
         }
+
+        m
     }
 
-    let mut freq_map:HashMap<char, u64> = HashMap::new();
+    fn initial_pairs(&self) -> Vec<u128> {
+        let e = self.elements.len();
+        let mut pairs = vec![0; e * e];
 
-    for c in source.chars() {
-        *freq_map.entry(c).or_insert(0) += 1;
+        for w in self.template.windows(2) {
+            pairs[self.pair_index(w[0], w[1])] += 1;
+        }
+
+        pairs
     }
 
-    let mut min_char = ' ';
-    let mut min_num = u64::MAX;
-    let mut max_char = ' ';
-    let mut max_num = u64::MIN;
+    fn pairs_after(&self, steps: u64, modulus: Option<u128>) -> Option<Vec<u128>> {
+        self.transition_matrix().pow(steps, modulus)?.apply(&self.initial_pairs(), modulus)
+    }
 
-    for (key, value) in freq_map {
-        if value < min_num {
-            min_char = key;
-            min_num = value;
+    // Every element starts exactly one pair except the last, which never changes
+    fn freq_map(&self, pairs: &[u128], modulus: Option<u128>) -> Option<HashMap<char, u128>> {
+        let e = self.elements.len();
+        let mut counts = vec![0u128; e];
+
+        for (p, &num) in pairs.iter().enumerate() {
+            counts[p / e] = accumulate(counts[p / e], num, 1, modulus)?;
         }
 
-        if value > max_num {
-            max_char = key;
-            max_num = value;
+        if let Some(&last) = self.template.last() {
+            counts[last] = accumulate(counts[last], 1, 1, modulus)?;
         }
+
+        Some(self.elements.iter().copied().zip(counts).collect())
     }
 
-    let difference = max_num - min_num;
-    println!("The difference between the number of the most and least common element is {}", difference);
-    println!("The most common element is {} and the least common is {}", max_char, min_char);
-}
+    fn report(&self, steps: u64) -> Option<PolymerReport> {
+        let e = self.elements.len();
+        let transition = self.transition_matrix();
+        let mut pairs = self.initial_pairs();
+        let mut seen_pairs: Vec<bool> = pairs.iter().map(|&n| n > 0).collect();
+        let mut reports = Vec::new();
+
+        for step in 0..=steps {
+            let mut rule_usage = HashMap::new();
+            if step > 0 {
+                for &(a, b) in self.pair_ins_map.keys() {
+                    rule_usage.insert((a, b), pairs[self.pair_index(a, b)]);
+                }
+
+                pairs = transition.apply(&pairs, None)?;
+                for (seen, &n) in seen_pairs.iter_mut().zip(&pairs) {
+                    *seen |= n > 0;
+                }
+            }
 
-fn run_part_02() {
-    let file = File::open("input/day14.txt").expect("Failed to open file");
-    let reader = BufReader::new(file);
+            let freq_map = self.freq_map(&pairs, None)?;
+            let length = freq_map.values().try_fold(0u128, |acc, &n| acc.checked_add(n))?;
+            reports.push(StepReport { step, freq_map, length, rule_usage });
+        }
 
-    let mut lines = reader.lines();
+        let missing_pairs = (0..e * e)
+            .filter(|&p| seen_pairs[p] && !self.pair_ins_map.contains_key(&(p / e, p % e)))
+            .map(|p| (self.elements[p / e], self.elements[p % e]))
+            .collect();
 
-    let source = lines.next().expect("Failed to parse first line").expect("Failed to parse first line");
-    lines.next(); //Skip empty line
+        Some(PolymerReport { steps: reports, missing_pairs })
+    }
 
-    let mut pair_ins_map:HashMap<String, String> = HashMap::new();
+    // One row per step: step, length, then a count per element and a firing count per rule
+    fn write_csv(&self, report: &PolymerReport, out: &mut impl Write) -> io::Result<()> {
+        let mut rules: Vec<(usize, usize)> = self.pair_ins_map.keys().copied().collect();
+        rules.sort_by_key(|&(a, b)| (self.elements[a], self.elements[b]));
+        let mut elements = self.elements.clone();
+        elements.sort_unstable();
 
-    for line in lines {
-        let l = line.expect("Failed to parse line");
+        write!(out, "step,length")?;
+        for e in &elements {
+            write!(out, ",{}", e)?;
+        }
+        for (a, b) in &rules {
+            write!(out, ",{}{}->{}", self.elements[*a], self.elements[*b], self.elements[self.pair_ins_map[&(*a, *b)]])?;
+        }
+        writeln!(out)?;
 
-        let split = l.split(" -> ").collect::<Vec<&str>>();
+        for step in &report.steps {
+            write!(out, "{},{}", step.step, step.length)?;
+            for e in &elements {
+                write!(out, ",{}", step.freq_map[e])?;
+            }
+            for rule in &rules {
+                write!(out, ",{}", step.rule_usage.get(rule).copied().unwrap_or(0))?;
+            }
+            writeln!(out)?;
+        }
 
-        assert_eq!(split.len(), 2);
+        Ok(())
+    }
 
-        pair_ins_map.insert(split[0].to_owned(), split[1].to_owned());
+    fn freq_map_after(&self, steps: u64, modulus: Option<u128>) -> Option<HashMap<char, u128>> {
+        self.freq_map(&self.pairs_after(steps, modulus)?, modulus)
     }
+}
 
-    let mut active_pairs:HashMap<String, u64> = HashMap::new();
-    let mut freq_map:HashMap<char, u64> = HashMap::new();
+pub fn run() {
+    println!("--Part 1");
+    run_part_01();
+    println!("--Part 2");
+    run_part_02();
+    println!("--Far future");
+    run_far_future();
+}
 
-    for i in 1..source.len() {
-        *active_pairs.entry(source[(i - 1)..=i].to_owned()).or_insert(0) += 1;
-    }
+fn run_part_01() {
+    let polymer = Polymer::load("input/day14.txt");
 
-    source.chars().for_each(|c| *freq_map.entry(c).or_default() += 1);
+    let freq_map = polymer.freq_map_after(10, None).expect("Element counts overflowed");
+    print_difference(&freq_map);
+}
 
-    let iterations = 40;
+fn run_part_02() {
+    let polymer = Polymer::load("input/day14.txt");
 
-    for _ in 0..iterations {
-        let mut new_pairs:HashMap<String, u64> = HashMap::new();
+    let freq_map = polymer.freq_map_after(40, None).expect("Element counts overflowed");
+    print_difference(&freq_map);
 
-        for (mut pair, num) in active_pairs.into_iter() {
-            match pair_ins_map.get(&pair) {
-                Some(ins) => {
-                    *freq_map.entry(ins.chars().next().unwrap()).or_default() += num;
-                    pair.insert_str(1, ins);
-                    *new_pairs.entry(pair[0..=1].to_owned()).or_insert(0) += num;
-                    *new_pairs.entry(pair[1..=2].to_owned()).or_insert(0) += num;
-                },
-                None => *new_pairs.entry(pair).or_insert(0) += num
-            }
-        }
+    let report = polymer.report(40).expect("Element counts overflowed");
+    if report.missing_pairs.is_empty() {
+        println!("Every pair in the polymer has an insertion rule");
+    } else {
+        let missing: Vec<String> = report.missing_pairs.iter().map(|(a, b)| format!("{}{}", a, b)).collect();
+        println!("These pairs have no insertion rule and never grow: {}", missing.join(", "));
+    }
 
-        active_pairs = new_pairs;
+    if env::args().nth(2).as_deref() == Some("csv") {
+        fs::create_dir_all("output").expect("Failed to create output directory");
+        let mut out = BufWriter::new(File::create(REPORT_PATH).expect("Failed to create report"));
+        polymer.write_csv(&report, &mut out).and_then(|_| out.flush()).expect("Failed to write report");
+        println!("Wrote {} steps to {}", report.steps.len(), REPORT_PATH);
     }
+}
+
+fn run_far_future() {
+    let polymer = Polymer::load("input/day14.txt");
+
+    let steps = 1_000_000;
+    let freq_map = polymer.freq_map_after(steps, Some(FAR_MODULUS)).expect("Modular counts cannot overflow");
+    let length = freq_map.values().fold(0, |acc, &n| (acc + n) % FAR_MODULUS);
+
+    println!("After {} steps the polymer length is {} modulo {}", steps, length, FAR_MODULUS);
+}
 
+fn print_difference(freq_map: &HashMap<char, u128>) {
     let mut min_char = ' ';
-    let mut min_num = u64::MAX;
+    let mut min_num = u128::MAX;
     let mut max_char = ' ';
-    let mut max_num = u64::MIN;
+    let mut max_num = u128::MIN;
+
+    for (&key, &value) in freq_map {
+        if value == 0 {
+            continue;
+        }
 
-    for (key, value) in freq_map {
         if value < min_num {
             min_char = key;
             min_num = value;
@@ -146,4 +270,4 @@ fn run_part_02() {
     let difference = max_num - min_num;
     println!("The difference between the number of the most and least common element is {}", difference);
     println!("The most common element is {} and the least common is {}", max_char, min_char);
-}
\ No newline at end of file
+}
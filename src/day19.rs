@@ -1,22 +1,189 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
 type Point = (i32, i32, i32);
 type Observation = Vec<Point>;
 
-struct PlacedObservation {
-    points: Observation,
+// One of the 24 proper rotations of the cube, stored as a 3x3 signed permutation matrix
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct Rotation {
+    m: [[i32; 3]; 3],
+}
+
+impl Rotation {
+    const IDENTITY: Rotation = Rotation {
+        m: [[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+    };
+
+    fn all() -> Vec<Rotation> {
+        let perms = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+
+        let mut rotations = Vec::with_capacity(24);
+        for perm in perms {
+            for signs in 0..8 {
+                let mut m = [[0; 3]; 3];
+                for (row, &col) in perm.iter().enumerate() {
+                    m[row][col] = if signs & (1 << row) != 0 { -1 } else { 1 };
+                }
+
+                let rotation = Rotation { m };
+                if rotation.determinant() == 1 {
+                    rotations.push(rotation);
+                }
+            }
+        }
+
+        rotations
+    }
+
+    fn determinant(&self) -> i32 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // The rotation that applies other first, then self
+    fn compose(&self, other: &Rotation) -> Rotation {
+        let mut m = [[0; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                *cell = (0..3).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+
+        Rotation { m }
+    }
+
+    #[cfg(test)]
+    fn inverse(&self) -> Rotation {
+        let mut m = [[0; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                *cell = self.m[j][i];
+            }
+        }
+
+        Rotation { m }
+    }
+
+    fn apply(&self, p: Point) -> Point {
+        let m = &self.m;
+        (
+            m[0][0] * p.0 + m[0][1] * p.1 + m[0][2] * p.2,
+            m[1][0] * p.0 + m[1][1] * p.1 + m[1][2] * p.2,
+            m[2][0] * p.0 + m[2][1] * p.1 + m[2][2] * p.2,
+        )
+    }
+
+    fn apply_all(&self, points: &[Point]) -> Observation {
+        points.iter().map(|&p| self.apply(p)).collect()
+    }
+}
+
+// Maps a point from a scanner's frame into a parent frame: rotate, then offset
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Transform {
+    rotation: Rotation,
     offset: Point,
-    distances: HashSet<i32>,
 }
 
-struct UnplacedObservation {
-    points: Observation,
-    distances: HashSet<i32>,
+impl Transform {
+    const IDENTITY: Transform = Transform {
+        rotation: Rotation::IDENTITY,
+        offset: (0, 0, 0),
+    };
+
+    // The transform that applies other first, then self
+    fn compose(&self, other: &Transform) -> Transform {
+        Transform {
+            rotation: self.rotation.compose(&other.rotation),
+            offset: self.apply(other.offset),
+        }
+    }
+
+    #[cfg(test)]
+    fn inverse(&self) -> Transform {
+        let rotation = self.rotation.inverse();
+        let o = rotation.apply(self.offset);
+        Transform {
+            rotation,
+            offset: (-o.0, -o.1, -o.2),
+        }
+    }
+
+    fn apply(&self, p: Point) -> Point {
+        add(self.rotation.apply(p), self.offset)
+    }
+}
+
+type Fingerprint = (i32, i32, i32);
+
+// Number of nearest neighbours each beacon forms triangles with
+const NEAREST: usize = 3;
+const MIN_OVERLAP: usize = 12;
+
+// Three beacons of one scanner, ordered by the length of the edge opposite each vertex so that the
+// same triangle seen from another scanner lists its vertices in the same order
+struct Triangle {
+    key: Fingerprint,
+    vertices: [usize; 3],
+}
+
+impl Triangle {
+    fn new(points: &[Point], v: [usize; 3]) -> Option<Self> {
+        let mut by_opposite = [
+            (sq_dist(points[v[1]], points[v[2]]), v[0]),
+            (sq_dist(points[v[0]], points[v[2]]), v[1]),
+            (sq_dist(points[v[0]], points[v[1]]), v[2]),
+        ];
+        by_opposite.sort_unstable();
+
+        // An isosceles triangle doesn't pin down which vertex is which
+        if by_opposite[0].0 == by_opposite[1].0 || by_opposite[1].0 == by_opposite[2].0 {
+            return None;
+        }
+
+        Some(Triangle {
+            key: (by_opposite[0].0, by_opposite[1].0, by_opposite[2].0),
+            vertices: [by_opposite[0].1, by_opposite[1].1, by_opposite[2].1],
+        })
+    }
+}
+
+struct FingerprintIndex {
+    triangles: Vec<Vec<Triangle>>,
+    by_key: HashMap<Fingerprint, Vec<(usize, usize)>>,
+}
+
+impl FingerprintIndex {
+    fn new(scanners: &[Observation]) -> Self {
+        let triangles: Vec<Vec<Triangle>> = scanners.iter().map(|s| triangles_of(s)).collect();
+
+        let mut by_key: HashMap<Fingerprint, Vec<(usize, usize)>> = HashMap::new();
+        for (scanner, tris) in triangles.iter().enumerate() {
+            for (i, tri) in tris.iter().enumerate() {
+                by_key.entry(tri.key).or_default().push((scanner, i));
+            }
+        }
+
+        FingerprintIndex { triangles, by_key }
+    }
+
+    // Every (other scanner, its triangle, our triangle) sharing a fingerprint with the given scanner
+    fn candidates(&self, scanner: usize) -> impl Iterator<Item = (usize, &Triangle, &Triangle)> + '_ {
+        self.triangles[scanner].iter().flat_map(move |ours| {
+            self.by_key[&ours.key]
+                .iter()
+                .filter(move |(other, _)| *other != scanner)
+                .map(move |&(other, i)| (other, &self.triangles[other][i], ours))
+        })
+    }
 }
 
 pub fn run() {
+    println!("--Part 1");
     run_part_01();
 }
 
@@ -24,8 +191,6 @@ fn run_part_01() {
     let file = File::open("input/day19.txt").expect("Failed to open file");
     let reader = BufReader::new(file);
 
-    let mut known_set: HashSet<Point> = HashSet::new();
-
     let mut data: Vec<Observation> = Vec::new();
     data.push(Vec::new());
 
@@ -44,7 +209,7 @@ fn run_part_01() {
             continue;
         }
 
-        let split: Vec<&str> = l.split(",").collect();
+        let split: Vec<&str> = l.split(',').collect();
         assert_eq!(split.len(), 3);
 
         let x = split[0].parse::<i32>().expect("Failed to parse int");
@@ -54,67 +219,112 @@ fn run_part_01() {
         data[obvs_load_head].push((x, y, z));
     }
 
-    let mut unplaced_observations: Vec<UnplacedObservation> = Vec::new();
+    let transforms = place_scanners(&data);
+    let placed: Vec<Transform> = transforms.iter().flatten().copied().collect();
+    assert_eq!(placed.len(), data.len(), "Failed to place every scanner");
 
-    for obs in &data {
-        let mut dists: HashSet<i32> = HashSet::new();
-        for p in obs {
-            for p2 in obs {
-                if p == p2 {
-                    continue;
-                }
+    let mut known_set: HashSet<Point> = HashSet::new();
+    for (obs, transform) in data.iter().zip(&placed) {
+        for point in obs {
+            known_set.insert(transform.apply(*point));
+        }
+    }
 
-                let dif = subtract(p.clone(), p2.clone());
+    let known = known_set.len();
+    println!("There are {} points", known);
+
+    let mut max_manhattan = 0;
+    for i in &placed {
+        for k in &placed {
+            let d = dist(i.offset, k.offset);
 
-                dists.insert(dif.0 * dif.0 + dif.1 * dif.1 + dif.2 * dif.2);
+            if d > max_manhattan {
+                max_manhattan = d;
             }
         }
+    }
+
+    println!("The max distance is {}", max_manhattan);
+}
+
+// Places every scanner it can relative to scanner 0, proposing alignments from shared triangle
+// fingerprints and falling back to trying every rotation for whatever that misses
+fn place_scanners(scanners: &[Observation]) -> Vec<Option<Transform>> {
+    let rotations = Rotation::all();
+    assert_eq!(rotations.len(), 24);
+
+    let mut transforms = place_by_fingerprints(scanners, &rotations);
 
-        unplaced_observations.push(UnplacedObservation {
-            points: obs.clone(),
-            distances: dists,
-        });
+    if transforms.iter().any(|t| t.is_none()) {
+        place_brute_force(scanners, &mut transforms, &rotations);
     }
 
-    let mut placed_observations: Vec<PlacedObservation> = Vec::new();
+    transforms
+}
 
-    let first = unplaced_observations.swap_remove(0);
-    placed_observations.push(PlacedObservation {
-        points: first.points,
-        offset: (0, 0, 0),
-        distances: first.distances,
-    });
+// Places whatever the fingerprint index can reach from scanner 0, leaving the rest as None
+fn place_by_fingerprints(scanners: &[Observation], rotations: &[Rotation]) -> Vec<Option<Transform>> {
+    let index = FingerprintIndex::new(scanners);
+    let point_sets: Vec<HashSet<Point>> = scanners.iter().map(|s| s.iter().copied().collect()).collect();
 
-    for point in &placed_observations[0].points {
-        known_set.insert(point.clone());
+    let mut transforms: Vec<Option<Transform>> = vec![None; scanners.len()];
+    transforms[0] = Some(Transform::IDENTITY);
+
+    let mut queue: VecDeque<usize> = VecDeque::new();
+    queue.push_back(0);
+
+    while let Some(placed) = queue.pop_front() {
+        let placed_transform = transforms[placed].expect("Queued scanner was not placed");
+
+        for (other, theirs, ours) in index.candidates(placed) {
+            if transforms[other].is_some() {
+                continue;
+            }
+
+            let relative = match align_triangles(&scanners[placed], ours, &scanners[other], theirs, rotations) {
+                Some(t) => t,
+                None => continue,
+            };
+
+            if count_overlap(&scanners[other], &relative, &point_sets[placed]) >= MIN_OVERLAP {
+                transforms[other] = Some(placed_transform.compose(&relative));
+                queue.push_back(other);
+            }
+        }
     }
 
-    let mut nf = 0;
-    'outer: while unplaced_observations.len() != 0 {
-        for i in 0..unplaced_observations.len() {
-            for placed in &placed_observations {
-                if !check_distances(&unplaced_observations[i].distances, &placed) {
-                    nf += 1;
+    transforms
+}
+
+fn place_brute_force(scanners: &[Observation], transforms: &mut [Option<Transform>], rotations: &[Rotation]) {
+    let distances: Vec<HashSet<i32>> = scanners.iter().map(|s| distances_of(s)).collect();
+
+    'outer: loop {
+        for i in 0..scanners.len() {
+            if transforms[i].is_some() {
+                continue;
+            }
+
+            for j in 0..scanners.len() {
+                let placed_transform = match transforms[j] {
+                    Some(t) => t,
+                    None => continue,
+                };
+
+                if !check_distances(&distances[i], &distances[j]) {
                     continue;
                 }
-                for rotation in 0..24 {
-                    let rotated = rotate(&unplaced_observations[i].points, rotation);
-                    match check_pair(&rotated, placed) {
-                        Some(p) => {
-                            let r = unplaced_observations.swap_remove(i);
-                            let new_obs = PlacedObservation {
-                                points: rotated,
-                                offset: p,
-                                distances: r.distances,
-                            };
-                            for point in &new_obs.points {
-                                known_set.insert(add(point.clone(), new_obs.offset));
-                            }
-
-                            placed_observations.push(new_obs);
-                            continue 'outer;
-                        }
-                        None => (),
+
+                for rotation in rotations {
+                    let rotated = rotation.apply_all(&scanners[i]);
+                    if let Some(offset) = check_pair(&rotated, &scanners[j]) {
+                        // Relative to the placed scanner's own frame, then chained onto its transform
+                        let relative = Transform {
+                            rotation: *rotation,
+                            offset,
+                        };
+                        transforms[i] = Some(placed_transform.compose(&relative));
+                        continue 'outer;
                     }
                 }
             }
@@ -122,42 +332,95 @@ fn run_part_01() {
 
         break;
     }
-    println!("{}", nf);
+}
 
-    let known = known_set.len();
-    println!("There are {} points", known);
+// Solves for the transform taking the source triangle onto the target one, if any rotation does
+fn align_triangles(
+    target: &[Point],
+    target_tri: &Triangle,
+    source: &[Point],
+    source_tri: &Triangle,
+    rotations: &[Rotation],
+) -> Option<Transform> {
+    let t = target_tri.vertices.map(|v| target[v]);
+    let s = source_tri.vertices.map(|v| source[v]);
+
+    let t1 = subtract(t[1], t[0]);
+    let t2 = subtract(t[2], t[0]);
+    let s1 = subtract(s[1], s[0]);
+    let s2 = subtract(s[2], s[0]);
+
+    rotations
+        .iter()
+        .find(|r| r.apply(s1) == t1 && r.apply(s2) == t2)
+        .map(|r| Transform {
+            rotation: *r,
+            offset: subtract(t[0], r.apply(s[0])),
+        })
+}
 
-    let mut max_manhattan = 0;
-    for i in &placed_observations {
-        for k in &placed_observations {
-            let d = dist(i.offset, k.offset);
+fn count_overlap(points: &[Point], transform: &Transform, with: &HashSet<Point>) -> usize {
+    points.iter().filter(|&&p| with.contains(&transform.apply(p))).count()
+}
 
-            if d > max_manhattan {
-                max_manhattan = d;
+fn triangles_of(points: &[Point]) -> Vec<Triangle> {
+    let mut seen: HashSet<[usize; 3]> = HashSet::new();
+    let mut triangles = Vec::new();
+
+    for i in 0..points.len() {
+        let mut near: Vec<usize> = (0..points.len()).filter(|&j| j != i).collect();
+        near.sort_by_key(|&j| sq_dist(points[i], points[j]));
+        near.truncate(NEAREST);
+
+        for a in 0..near.len() {
+            for b in (a + 1)..near.len() {
+                let mut v = [i, near[a], near[b]];
+                v.sort_unstable();
+
+                if !seen.insert(v) {
+                    continue;
+                }
+
+                if let Some(tri) = Triangle::new(points, v) {
+                    triangles.push(tri);
+                }
             }
         }
     }
 
-    println!("The max distance is {}", max_manhattan);
+    triangles
 }
 
-fn check_pair(points: &Observation, with: &PlacedObservation) -> Option<Point> {
-    for i in 0..with.points.len() {
-        for q in 0..points.len() {
-            let offset = subtract(with.points[i], points[q]);
+fn distances_of(points: &[Point]) -> HashSet<i32> {
+    let mut dists: HashSet<i32> = HashSet::new();
+    for p in points {
+        for p2 in points {
+            if p == p2 {
+                continue;
+            }
 
-            let mut num_match = 0;
-            for j in 0..points.len() {
-                for k in 0..with.points.len() {
-                    if add(points[j], offset) == with.points[k] {
-                        num_match += 1;
-                        continue;
-                    }
+            dists.insert(sq_dist(*p, *p2));
+        }
+    }
 
-                    if num_match >= 12 {
-                        return Some(add(offset, with.offset));
-                    }
-                }
+    dists
+}
+
+// Finds the offset that lines up at least 12 of the given points with the placed scanner's points, in that scanner's frame
+fn check_pair(points: &[Point], with: &[Point]) -> Option<Point> {
+    let with_set: HashSet<&Point> = with.iter().collect();
+
+    for anchor in with {
+        for p in points {
+            let offset = subtract(*anchor, *p);
+
+            let num_match = points
+                .iter()
+                .filter(|&&q| with_set.contains(&add(q, offset)))
+                .count();
+
+            if num_match >= MIN_OVERLAP {
+                return Some(offset);
             }
         }
     }
@@ -165,54 +428,20 @@ fn check_pair(points: &Observation, with: &PlacedObservation) -> Option<Point> {
     None
 }
 
-fn check_distances(point_distances: &HashSet<i32>, with: &PlacedObservation) -> bool {
+fn check_distances(point_distances: &HashSet<i32>, with: &HashSet<i32>) -> bool {
     let mut num_distance_match = 0;
 
     for i in point_distances {
-        if with.distances.contains(i) {
+        if with.contains(i) {
             num_distance_match += 1;
         }
 
-        if num_distance_match >= 12 {
+        if num_distance_match >= MIN_OVERLAP {
             return true;
         }
     }
 
-    return false;
-}
-
-fn rotate(points: &Observation, direction: u32) -> Observation {
-    points
-        .clone()
-        .into_iter()
-        .map(|x| match direction {
-            0 => (-x.2, x.1, x.0),
-            1 => (x.2, x.1, -x.0),
-            2 => x,
-            3 => (-x.0, x.1, -x.2),
-            4 => (x.2, -x.1, x.0),
-            5 => (-x.2, -x.1, -x.0),
-            6 => (-x.0, -x.1, x.2),
-            7 => (x.0, -x.1, -x.2),
-            8 => (x.2, -x.0, -x.1),
-            9 => (-x.2, -x.0, x.1),
-            10 => (x.1, -x.0, x.2),
-            11 => (-x.1, -x.0, -x.2),
-            12 => (-x.2, x.0, -x.1),
-            13 => (x.2, x.0, x.1),
-            14 => (-x.1, x.0, x.2),
-            15 => (x.1, x.0, -x.2),
-            16 => (-x.0, -x.2, -x.1),
-            17 => (x.0, -x.2, x.1),
-            18 => (-x.1, -x.2, x.0),
-            19 => (x.1, -x.2, -x.0),
-            20 => (x.0, x.2, -x.1),
-            21 => (-x.0, x.2, x.1),
-            22 => (x.1, x.2, x.0),
-            23 => (-x.1, x.2, -x.0),
-            _ => panic!(),
-        })
-        .collect::<Observation>()
+    false
 }
 
 fn add(x1: Point, x2: Point) -> Point {
@@ -223,7 +452,129 @@ fn subtract(x1: Point, x2: Point) -> Point {
     (x1.0 - x2.0, x1.1 - x2.1, x1.2 - x2.2)
 }
 
+fn sq_dist(x1: Point, x2: Point) -> i32 {
+    let s = subtract(x1, x2);
+    s.0 * s.0 + s.1 * s.1 + s.2 * s.2
+}
+
 fn dist(x1: Point, x2: Point) -> i32 {
     let s = subtract(x1, x2);
     s.0.abs() + s.1.abs() + s.2.abs()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCANNER_RANGE: i32 = 1000;
+
+    struct SyntheticScanners {
+        observations: Vec<Observation>,
+        transforms: Vec<Transform>,
+    }
+
+    // xorshift64*, enough to generate repeatable synthetic inputs
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn range(&mut self, lo: i32, hi: i32) -> i32 {
+            lo + (self.next() % (hi - lo + 1) as u64) as i32
+        }
+    }
+
+    // Scatters beacons around a tree of scanners whose neighbours are at most 500 apart on each axis, then
+    // records what each scanner sees within range in its own randomly rotated frame
+    fn generate_scanners(num_scanners: usize, beacons_per_scanner: usize, seed: u64) -> SyntheticScanners {
+        let mut rng = Rng(seed);
+        let rotations = Rotation::all();
+
+        let mut positions: Vec<Point> = vec![(0, 0, 0)];
+        while positions.len() < num_scanners {
+            let from = positions[rng.next() as usize % positions.len()];
+            let step = (rng.range(-500, 500), rng.range(-500, 500), rng.range(-500, 500));
+            positions.push(add(from, step));
+        }
+
+        let mut min = positions[0];
+        let mut max = positions[0];
+        for p in &positions {
+            min = (min.0.min(p.0), min.1.min(p.1), min.2.min(p.2));
+            max = (max.0.max(p.0), max.1.max(p.1), max.2.max(p.2));
+        }
+        min = subtract(min, (SCANNER_RANGE, SCANNER_RANGE, SCANNER_RANGE));
+        max = add(max, (SCANNER_RANGE, SCANNER_RANGE, SCANNER_RANGE));
+
+        let cube = (2 * SCANNER_RANGE + 1) as f64;
+        let volume = (max.0 - min.0 + 1) as f64 * (max.1 - min.1 + 1) as f64 * (max.2 - min.2 + 1) as f64;
+        let num_beacons = (beacons_per_scanner as f64 * volume / (cube * cube * cube)) as usize;
+
+        let mut beacons: HashSet<Point> = HashSet::new();
+        while beacons.len() < num_beacons {
+            beacons.insert((rng.range(min.0, max.0), rng.range(min.1, max.1), rng.range(min.2, max.2)));
+        }
+
+        let mut observations = Vec::with_capacity(num_scanners);
+        let mut transforms = Vec::with_capacity(num_scanners);
+        for pos in positions {
+            let transform = Transform {
+                rotation: rotations[rng.next() as usize % rotations.len()],
+                offset: pos,
+            };
+            let to_local = transform.inverse();
+
+            let mut seen: Observation = beacons
+                .iter()
+                .filter(|b| {
+                    let d = subtract(**b, pos);
+                    d.0.abs() <= SCANNER_RANGE && d.1.abs() <= SCANNER_RANGE && d.2.abs() <= SCANNER_RANGE
+                })
+                .map(|b| to_local.apply(*b))
+                .collect();
+
+            // HashSet order already scrambles these, but make it repeatable across runs
+            seen.sort_unstable();
+            for i in (1..seen.len()).rev() {
+                seen.swap(i, rng.next() as usize % (i + 1));
+            }
+
+            observations.push(seen);
+            transforms.push(transform);
+        }
+
+        SyntheticScanners { observations, transforms }
+    }
+
+    #[test]
+    fn rotations_form_a_group() {
+        let rotations = Rotation::all();
+
+        assert_eq!(rotations.len(), 24);
+        assert!(rotations.contains(&Rotation::IDENTITY));
+        for r in &rotations {
+            assert!(rotations.contains(&r.inverse()));
+            assert_eq!(r.compose(&r.inverse()), Rotation::IDENTITY);
+            assert!(rotations.iter().all(|o| rotations.contains(&r.compose(o))));
+        }
+    }
+
+    #[test]
+    fn places_synthetic_scanners() {
+        let synthetic = generate_scanners(200, 80, 0x5eed_2021);
+
+        // The index alone should place everything, without leaning on the brute force fallback
+        let transforms = place_by_fingerprints(&synthetic.observations, &Rotation::all());
+
+        // Everything is placed relative to scanner 0, so compare against the truth in that frame
+        let to_first = synthetic.transforms[0].inverse();
+        for (i, (found, truth)) in transforms.iter().zip(&synthetic.transforms).enumerate() {
+            assert_eq!(*found, Some(to_first.compose(truth)), "scanner {}", i);
+        }
+    }
+}
@@ -1,203 +1,241 @@
-use std::{collections::HashMap, fs::File, io::{BufReader, BufRead}};
+use std::{env, fs::{self, File}, io::{self, BufReader, BufRead, BufWriter, Write}};
 
-type Coord = (i32, i32);
+const WORD_BITS: usize = 64;
 
+// Pixels packed 64 to a word, row by row. Everything outside the stored rectangle has the background value,
+// which can itself be lit once the enhancement algorithm maps an all-dark neighbourhood to a lit pixel
+#[derive(Clone)]
 struct Image {
-    image: HashMap<Coord, char>,
-    min: Coord,
-    max: Coord,
-    overmin: Coord,
-    overmax: Coord,
+    rows: Vec<Vec<u64>>,
+    width: usize,
+    height: usize,
+    background: bool,
 }
 
 impl Image {
-    fn new(data: &Vec<char>, mini: Coord, maxi: Coord, over:i32) -> Self {
-        let mut image = Image { image: HashMap::new(), min: mini, max: maxi, overmin: (mini.0 - over, mini.1 - over), overmax: (maxi.0 + over, maxi.1 + over) };
-        
-        let width = maxi.0 - mini.0;
-
-        for i in image.min.0..image.max.0 {
-            for j in image.min.1..image.max.1 {
-                let coord = (i, j);
-                image.image.insert(coord, data[(i + j * width) as usize]);
-            }
-        }
-
-        for i in image.overmin.0..image.overmax.0 {
-            for j in image.overmin.1..image.overmax.1 {
-                let coord = (i, j);
-                image.image.entry(coord).or_insert('0');
+    fn new(lines: &[String]) -> Self {
+        let width = lines.first().map_or(0, |l| l.len());
+        let height = lines.len();
+
+        let mut image = Image { rows: Vec::with_capacity(height), width, height, background: false };
+
+        for line in lines {
+            let mut row = vec![0; Image::words_for(width)];
+            for (x, c) in line.chars().enumerate() {
+                if c == '#' {
+                    row[x / WORD_BITS] |= 1 << (x % WORD_BITS);
+                }
             }
+            image.rows.push(row);
         }
 
         image
     }
-    
-    fn get_at(&self, x: &Coord) -> char {
-        match self.image.get(x) {
-            Some(n) => *n,
-            None => '0',
-        }
+
+    fn words_for(width: usize) -> usize {
+        width.div_ceil(WORD_BITS)
     }
 
-    fn get_lookup_for(&self, x: &Coord) -> usize {
-        let mut s = String::new();
-        
-        for k in (x.1 - 1)..=(x.1 + 1_) {
-            for i in (x.0 - 1)..=(x.0 + 1) {
-                s.push(self.get_at(&(i, k)));
-            }
+    fn get_at(&self, x: i64, y: i64) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return self.background;
         }
 
-        //println!("{}", s);
-        return usize::from_str_radix(&s, 2).expect("Failed to parse lookup")
+        let (x, y) = (x as usize, y as usize);
+        self.rows[y][x / WORD_BITS] & (1 << (x % WORD_BITS)) != 0
     }
 
-    fn enhance(&mut self, enhancement_alg: &Vec<char>) {
-        let mut new = HashMap::new();
+    fn get_lookup_for(&self, x: i64, y: i64) -> usize {
+        let mut lookup = 0;
 
-        for i in self.overmin.0..self.overmax.0 {
-            for j in self.overmin.1..self.overmax.1 {
-                let lookup = self.get_lookup_for(&(i, j));
-                new.insert((i, j), enhancement_alg[lookup]);
+        for k in (y - 1)..=(y + 1) {
+            for i in (x - 1)..=(x + 1) {
+                lookup = (lookup << 1) | self.get_at(i, k) as usize;
             }
         }
 
-        self.min = (self.min.0 - 1, self.min.1 - 1);
-        self.max = (self.max.0 + 1, self.max.1 + 1);
-        self.image = new;
+        lookup
     }
 
-    fn count(&self) -> i32 {
-        let mut num = 0;
-        for i in self.min.0..self.max.0 {
-            for j in self.min.1..self.max.1 {
-                if self.get_at(&(i, j)) == '1' {
-                    num += 1;
-                }    
+    // Grows by one pixel on every side, the furthest the background can influence in a single step
+    fn enhance(&mut self, enhancement_alg: &[bool]) {
+        let width = self.width + 2;
+        let height = self.height + 2;
+
+        let mut rows = Vec::with_capacity(height);
+        for y in 0..height {
+            let mut row = vec![0; Image::words_for(width)];
+            for x in 0..width {
+                let lookup = self.get_lookup_for(x as i64 - 1, y as i64 - 1);
+                if enhancement_alg[lookup] {
+                    row[x / WORD_BITS] |= 1 << (x % WORD_BITS);
+                }
             }
+            rows.push(row);
         }
 
-        num
+        self.background = if self.background { enhancement_alg[511] } else { enhancement_alg[0] };
+        self.rows = rows;
+        self.width = width;
+        self.height = height;
     }
 
-    fn _display(&self) {
-        for j in self.min.1..self.max.1 {
-            for i in self.min.0..self.max.0 {
-                print!("{}", match self.get_at(&(i, j))
-            {
-                '1' => '#',
-                '0' => '.',
-                _ => '.'
-            });
-            }
-            println!("");
+    // None when the infinite background is lit
+    fn count(&self) -> Option<u32> {
+        if self.background {
+            return None;
         }
-        println!("");
+
+        Some(self.rows.iter().flatten().map(|w| w.count_ones()).sum())
     }
-}
 
-pub fn run() {
-    println!("--Part 1");
-    run_part_01();
-    println!("--Part 2");
-    run_part_02();
-}
+    // Writes a binary PBM frame of the given size with the image centred in it, so that frames from
+    // different generations line up. Lit pixels are black
+    fn write_pbm(&self, out: &mut impl Write, width: usize, height: usize) -> io::Result<()> {
+        assert!(width >= self.width && height >= self.height, "Frame is smaller than the image");
 
-fn run_part_01() {
-    let file = File::open("input/day20.txt").expect("Failed to open file");
-    let reader = BufReader::new(file);
+        let left = ((width - self.width) / 2) as i64;
+        let top = ((height - self.height) / 2) as i64;
 
-    let mut lines = reader.lines();
+        write!(out, "P4\n{} {}\n", width, height)?;
+
+        let mut row = vec![0u8; width.div_ceil(8)];
+        for y in 0..height as i64 {
+            for byte in row.iter_mut() {
+                *byte = 0;
+            }
 
-    let enhancement_alg:Vec<char> = lines.next().unwrap().unwrap().chars().map(|c|
-        match c {
-            '#' => '1',
-            '.' => '0',
-            _ => '0'
+            for x in 0..width as i64 {
+                if self.get_at(x - left, y - top) {
+                    row[x as usize / 8] |= 0x80 >> (x % 8);
+                }
+            }
+
+            out.write_all(&row)?;
         }
-    ).collect();
 
-    lines.next(); //skip
+        Ok(())
+    }
 
-    let mut image_data:Vec<char> = Vec::new();
-    let min = (0, 0);
-    let mut max = (0, 0);
+    fn _display(&self) {
+        for y in 0..self.height as i64 {
+            for x in 0..self.width as i64 {
+                print!("{}", if self.get_at(x, y) { '#' } else { '.' });
+            }
+            println!();
+        }
+        println!();
+    }
+}
 
-    for line in lines {
-        let l = line.unwrap();
+const EXPORT_DIR: &str = "output/day20";
 
-        max.0 = l.len() as i32;
-        max.1 += 1;
+enum Export {
+    // One PBM file per generation
+    Frames,
+    // Every generation concatenated into a single multi-image PBM stream
+    Sequence,
+}
 
-        let chars = l.chars().map(|c|
-            match c {
-                '#' => '1',
-                '.' => '0',
-                _ => '0'
+impl Export {
+    // Other days share the argument for their own modes, so anything else means no export
+    fn from_arg(arg: Option<&str>) -> Option<Self> {
+        match arg {
+            Some("frames") => Some(Export::Frames),
+            Some("sequence") => Some(Export::Sequence),
+            _ => None,
+        }
+    }
+
+    // Every frame is padded to the size of the last, which is the largest
+    fn write(&self, frames: &[Image]) -> io::Result<()> {
+        let last = match frames.last() {
+            Some(l) => l,
+            None => return Ok(()),
+        };
+        let (width, height) = (last.width, last.height);
+
+        fs::create_dir_all(EXPORT_DIR)?;
+
+        match self {
+            Export::Frames => {
+                for (step, frame) in frames.iter().enumerate() {
+                    let path = format!("{}/step_{:03}.pbm", EXPORT_DIR, step);
+                    let mut out = BufWriter::new(File::create(&path)?);
+                    frame.write_pbm(&mut out, width, height)?;
+                    out.flush()?;
+                }
+                println!("Wrote {} frames to {}", frames.len(), EXPORT_DIR);
+            }
+            Export::Sequence => {
+                let path = format!("{}/sequence.pbm", EXPORT_DIR);
+                let mut out = BufWriter::new(File::create(&path)?);
+                for frame in frames {
+                    frame.write_pbm(&mut out, width, height)?;
+                }
+                out.flush()?;
+                println!("Wrote {} frames to {}", frames.len(), path);
             }
-        );
-        for c in chars {
-            image_data.push(c);
         }
+
+        Ok(())
     }
+}
+
+pub fn run() {
+    println!("--Part 1");
+    run_part_01();
+    println!("--Part 2");
+    run_part_02();
+}
 
-    let mut image = Image::new(&image_data, min, max, 5);
+fn run_part_01() {
+    let (enhancement_alg, mut image) = load("input/day20.txt");
 
     for _ in 0..2 {
         image.enhance(&enhancement_alg);
     }
 
-    let num = image.count();
+    let num = image.count().expect("Infinitely many pixels are lit");
 
     println!("There were {} lit pixels after twice enhancement", num);
 }
 
 fn run_part_02() {
-    let file = File::open("input/day20.txt").expect("Failed to open file");
-    let reader = BufReader::new(file);
+    let (enhancement_alg, mut image) = load("input/day20.txt");
 
-    let mut lines = reader.lines();
+    let export = Export::from_arg(env::args().nth(2).as_deref());
+    let mut frames = Vec::new();
 
-    let enhancement_alg:Vec<char> = lines.next().unwrap().unwrap().chars().map(|c|
-        match c {
-            '#' => '1',
-            '.' => '0',
-            _ => '0'
+    for _ in 0..50 {
+        if export.is_some() {
+            frames.push(image.clone());
         }
-    ).collect();
+        image.enhance(&enhancement_alg);
+    }
 
-    lines.next(); //skip
+    if let Some(export) = export {
+        frames.push(image.clone());
+        export.write(&frames).expect("Failed to export frames");
+    }
 
-    let mut image_data:Vec<char> = Vec::new();
-    let min = (0, 0);
-    let mut max = (0, 0);
+    let num = image.count().expect("Infinitely many pixels are lit");
 
-    for line in lines {
-        let l = line.unwrap();
+    println!("There were {} lit pixels after 50x enhancement", num);
+}
 
-        max.0 = l.len() as i32;
-        max.1 += 1;
+fn load(path: &str) -> (Vec<bool>, Image) {
+    let file = File::open(path).expect("Failed to open file");
+    let reader = BufReader::new(file);
 
-        let chars = l.chars().map(|c|
-            match c {
-                '#' => '1',
-                '.' => '0',
-                _ => '0'
-            }
-        );
-        for c in chars {
-            image_data.push(c);
-        }
-    }
+    let mut lines = reader.lines();
 
-    let mut image = Image::new(&image_data, min, max, 100);
+    let enhancement_alg: Vec<bool> = lines.next().unwrap().unwrap().chars().map(|c| c == '#').collect();
+    assert_eq!(enhancement_alg.len(), 512);
 
-    for _ in 0..50 {
-        image.enhance(&enhancement_alg);
-    }
+    lines.next(); //skip
 
-    let num = image.count();
+    let image_lines: Vec<String> = lines.map(|l| l.unwrap()).filter(|l| !l.is_empty()).collect();
 
-    println!("There were {} lit pixels after 50x enhancement", num);
-}
\ No newline at end of file
+    (enhancement_alg, Image::new(&image_lines))
+}
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::env;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::panic;
@@ -15,6 +15,26 @@ struct Instruction {
     max: Point
 }
 
+#[derive(Clone, Copy, Debug)]
+enum Engine {
+    InclusionExclusion,
+    CuboidSet,
+    Compressed,
+    CrossCheck
+}
+
+impl Engine {
+    // Other days share the argument for their own modes, so anything else gets the default
+    fn from_arg(arg: Option<&str>) -> Self {
+        match arg {
+            Some("inclusion") => Engine::InclusionExclusion,
+            Some("compressed") => Engine::Compressed,
+            Some("check") => Engine::CrossCheck,
+            _ => Engine::CuboidSet
+        }
+    }
+}
+
 struct Block {
     on: Volume,
     off: Vec<Volume>
@@ -96,6 +116,132 @@ impl Volume {
     }
 }
 
+// Inclusive axis-aligned box in N dimensions
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Cuboid<const N: usize> {
+    min: [i64; N],
+    max: [i64; N]
+}
+
+impl<const N: usize> Cuboid<N> {
+    fn new(min: [i64; N], max: [i64; N]) -> Self {
+        Cuboid { min, max }
+    }
+
+    fn volume(&self) -> i64 {
+        (0..N).map(|axis| self.max[axis] - self.min[axis] + 1).product()
+    }
+
+    fn intersect(&self, other: &Cuboid<N>) -> Option<Cuboid<N>> {
+        let mut min = self.min;
+        let mut max = self.max;
+        for axis in 0..N {
+            min[axis] = min[axis].max(other.min[axis]);
+            max[axis] = max[axis].min(other.max[axis]);
+
+            if min[axis] > max[axis] {
+                return None;
+            }
+        }
+
+        Some(Cuboid { min, max })
+    }
+
+    fn contains(&self, point: &[i64; N]) -> bool {
+        (0..N).all(|axis| self.min[axis] <= point[axis] && point[axis] <= self.max[axis])
+    }
+
+    // Splits off slabs of self along each axis until only the overlap with other remains, which is dropped
+    fn subtract(&self, other: &Cuboid<N>) -> Vec<Cuboid<N>> {
+        let overlap = match self.intersect(other) {
+            Some(o) => o,
+            None => return vec![*self]
+        };
+
+        let mut pieces = Vec::new();
+        let mut remaining = *self;
+        for axis in 0..N {
+            if remaining.min[axis] < overlap.min[axis] {
+                let mut piece = remaining;
+                piece.max[axis] = overlap.min[axis] - 1;
+                pieces.push(piece);
+                remaining.min[axis] = overlap.min[axis];
+            }
+
+            if remaining.max[axis] > overlap.max[axis] {
+                let mut piece = remaining;
+                piece.min[axis] = overlap.max[axis] + 1;
+                pieces.push(piece);
+                remaining.max[axis] = overlap.max[axis];
+            }
+        }
+
+        pieces
+    }
+}
+
+// A set of lattice points stored as pairwise disjoint cuboids
+#[derive(Clone, Default, Debug)]
+struct CuboidSet<const N: usize> {
+    cuboids: Vec<Cuboid<N>>
+}
+
+impl<const N: usize> CuboidSet<N> {
+    fn new() -> Self {
+        CuboidSet { cuboids: Vec::new() }
+    }
+
+    fn union(&self, other: &CuboidSet<N>) -> CuboidSet<N> {
+        let mut result = self.difference(other);
+        result.cuboids.extend_from_slice(&other.cuboids);
+        result
+    }
+
+    fn difference(&self, other: &CuboidSet<N>) -> CuboidSet<N> {
+        let mut cuboids = self.cuboids.clone();
+        for removed in &other.cuboids {
+            cuboids = cuboids.iter().flat_map(|c| c.subtract(removed)).collect();
+        }
+
+        CuboidSet { cuboids }
+    }
+
+    // Both sides are disjoint, so their pairwise overlaps are too
+    fn intersection(&self, other: &CuboidSet<N>) -> CuboidSet<N> {
+        let cuboids = self.cuboids.iter()
+            .flat_map(|c| other.cuboids.iter().filter_map(move |o| c.intersect(o)))
+            .collect();
+
+        CuboidSet { cuboids }
+    }
+
+    fn volume(&self) -> i64 {
+        self.iter().map(|c| c.volume()).sum()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Cuboid<N>> {
+        self.cuboids.iter()
+    }
+
+    fn count_in(&self, region: &Cuboid<N>) -> i64 {
+        self.intersection(&CuboidSet::from(*region)).volume()
+    }
+
+    fn contains(&self, point: &[i64; N]) -> bool {
+        self.iter().any(|c| c.contains(point))
+    }
+
+    fn intersecting<'a>(&'a self, region: &'a Cuboid<N>) -> impl Iterator<Item = &'a Cuboid<N>> {
+        self.iter().filter(move |c| c.intersect(region).is_some())
+    }
+}
+
+impl<const N: usize> From<Cuboid<N>> for CuboidSet<N> {
+    fn from(cuboid: Cuboid<N>) -> Self {
+        CuboidSet { cuboids: vec![cuboid] }
+    }
+}
+
 pub fn run() {
     println!("--Part 1");
     run_part_01();
@@ -104,32 +250,69 @@ pub fn run() {
 }
 
 fn run_part_01() {
-    let file = File::open("input/day22test.txt").expect("Failed to open file");
-    let reader = BufReader::new(file);
-
-    let mut map:HashSet<Point> = HashSet::new();
+    let instructions = load_instructions("input/day22.txt");
 
-    let min = (-50, -50, -50);
-    let max = (50, 50, 50);
-
-    for line in reader.lines() {
-        run_instruction(&mut map, parse(line.expect("Failed to read line").as_str()), &min, &max);
-    }
+    let reactor = final_state(&instructions);
 
-    let num = map.len();
+    let center = Cuboid::new([-50, -50, -50], [50, 50, 50]);
+    let num = reactor.count_in(&center);
 
     println!("There are {} points on in the center", num);
+    println!("The origin is {}, and {} lit cuboids touch the center",
+        if reactor.contains(&[0, 0, 0]) { "on" } else { "off" },
+        reactor.intersecting(&center).count());
 }
 
 fn run_part_02() {
-    let file = File::open("input/day22.txt").expect("Failed to open file");
+    let instructions = load_instructions("input/day22.txt");
+
+    let engine = Engine::from_arg(env::args().nth(2).as_deref());
+    let num = count_on(&instructions, engine);
+
+    println!("There are {} points on", num);
+}
+
+fn load_instructions(path: &str) -> Vec<Instruction> {
+    let file = File::open(path).expect("Failed to open file");
     let reader = BufReader::new(file);
 
-    let mut list:Vec<Block> = Vec::new();
+    reader.lines()
+        .map(|line| parse(line.expect("Failed to read line").as_str()))
+        .collect()
+}
+
+fn final_state(instructions: &[Instruction]) -> CuboidSet<3> {
+    let mut reactor: CuboidSet<3> = CuboidSet::new();
+
+    for ins in instructions {
+        reactor = run_instruction(&reactor, ins);
+    }
+
+    reactor
+}
+
+fn count_on(instructions: &[Instruction], engine: Engine) -> i64 {
+    match engine {
+        Engine::InclusionExclusion => count_on_inclusion_exclusion(instructions),
+        Engine::CuboidSet => count_on_cuboid_set(instructions),
+        Engine::Compressed => count_on_compressed(instructions),
+        Engine::CrossCheck => {
+            let inclusion_exclusion = count_on_inclusion_exclusion(instructions);
+            let cuboid_set = count_on_cuboid_set(instructions);
+            let compressed = count_on_compressed(instructions);
+
+            assert_eq!(inclusion_exclusion, compressed, "Inclusion-exclusion and compressed engines disagree");
+            assert_eq!(cuboid_set, compressed, "Cuboid set and compressed engines disagree");
+
+            compressed
+        }
+    }
+}
 
-    for line in reader.lines() {
-        let ins = parse(line.expect("Failed to read line").as_str());
+fn count_on_inclusion_exclusion(instructions: &[Instruction]) -> i64 {
+    let mut list:Vec<Block> = Vec::new();
 
+    for ins in instructions {
         match ins.action {
             Action::Off => {
                 for block in list.iter_mut() {
@@ -147,36 +330,87 @@ fn run_part_02() {
         }
     }
 
-    let mut num = 0;
-    for block in list {
-        num += block.count_on();
-    }
-
+    list.iter().map(|block| block.count_on()).sum()
+}
 
-    println!("There are {} points on", num);
+fn count_on_cuboid_set(instructions: &[Instruction]) -> i64 {
+    final_state(instructions).volume()
 }
 
-fn run_instruction(map: &mut HashSet<Point>, ins: Instruction, min: &Point, max: &Point) {
-    let real_min_x = ins.min.0.max(min.0);
-    let real_min_y = ins.min.1.max(min.1);
-    let real_min_z = ins.min.2.max(min.2);
-    let real_max_x = ins.max.0.min(max.0);
-    let real_max_y = ins.max.1.min(max.1);
-    let real_max_z = ins.max.2.min(max.2);
-    for i in real_min_x..=real_max_x {
-        for j in real_min_y..=real_max_y {
-            for k in real_min_z..=real_max_z {
-
-                match ins.action {
-                    Action::On => {
-                        map.insert((i, j, k));
-                    },
-                    Action::Off => {
-                        map.remove(&(i, j, k));
-                    }
+// Splits space at every instruction boundary so each cell is entirely on or off, then replays the
+// instructions over the cells one x slab at a time to keep the grid two dimensional
+fn count_on_compressed(instructions: &[Instruction]) -> i64 {
+    let xs = boundaries(instructions.iter().map(|ins| (ins.min.0, ins.max.0)));
+    let ys = boundaries(instructions.iter().map(|ins| (ins.min.1, ins.max.1)));
+    let zs = boundaries(instructions.iter().map(|ins| (ins.min.2, ins.max.2)));
+
+    // Cell index ranges covered by each instruction, as [start, end)
+    let ranges: Vec<[(usize, usize); 3]> = instructions.iter()
+        .map(|ins| [
+            cell_range(&xs, ins.min.0, ins.max.0),
+            cell_range(&ys, ins.min.1, ins.max.1),
+            cell_range(&zs, ins.min.2, ins.max.2)
+        ])
+        .collect();
+
+    let nz = zs.len() - 1;
+    let mut slab = vec![false; (ys.len() - 1) * nz];
+    let mut total = 0;
+
+    for i in 0..(xs.len() - 1) {
+        for cell in slab.iter_mut() {
+            *cell = false;
+        }
+
+        for (ins, range) in instructions.iter().zip(&ranges) {
+            if i < range[0].0 || i >= range[0].1 {
+                continue;
+            }
+
+            let on = matches!(ins.action, Action::On);
+            for j in range[1].0..range[1].1 {
+                for cell in &mut slab[(j * nz + range[2].0)..(j * nz + range[2].1)] {
+                    *cell = on;
                 }
             }
         }
+
+        let mut area = 0;
+        for (j, row) in slab.chunks(nz).enumerate() {
+            let width: i64 = row.iter().enumerate()
+                .filter(|(_, &on)| on)
+                .map(|(k, _)| zs[k + 1] - zs[k])
+                .sum();
+
+            area += width * (ys[j + 1] - ys[j]);
+        }
+
+        total += area * (xs[i + 1] - xs[i]);
+    }
+
+    total
+}
+
+// Sorted cell edges along one axis, where cell i spans [edges[i], edges[i + 1])
+fn boundaries(spans: impl Iterator<Item = (i64, i64)>) -> Vec<i64> {
+    let mut edges: Vec<i64> = spans.flat_map(|(lo, hi)| [lo, hi + 1]).collect();
+    edges.sort_unstable();
+    edges.dedup();
+    edges
+}
+
+fn cell_range(edges: &[i64], lo: i64, hi: i64) -> (usize, usize) {
+    let start = edges.binary_search(&lo).expect("Missing lower boundary");
+    let end = edges.binary_search(&(hi + 1)).expect("Missing upper boundary");
+    (start, end)
+}
+
+fn run_instruction(reactor: &CuboidSet<3>, ins: &Instruction) -> CuboidSet<3> {
+    let cuboid = CuboidSet::from(Cuboid::new([ins.min.0, ins.min.1, ins.min.2], [ins.max.0, ins.max.1, ins.max.2]));
+
+    match ins.action {
+        Action::On => reactor.union(&cuboid),
+        Action::Off => reactor.difference(&cuboid)
     }
 }
 
@@ -214,4 +448,89 @@ fn min(p1: &Point, p2: &Point) -> Point {
 
 fn max(p1: &Point, p2: &Point) -> Point {
     (p1.0.max(p2.0), p1.1.max(p2.1), p1.2.max(p2.2))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: (i64, i64), y: (i64, i64)) -> CuboidSet<2> {
+        CuboidSet::from(Cuboid::new([x.0, y.0], [x.1, y.1]))
+    }
+
+    fn points(set: &CuboidSet<2>) -> Vec<[i64; 2]> {
+        (-1..=10).flat_map(|x| (-1..=10).map(move |y| [x, y])).filter(|p| set.contains(p)).collect()
+    }
+
+    fn assert_disjoint(set: &CuboidSet<2>) {
+        let cuboids: Vec<&Cuboid<2>> = set.iter().collect();
+        for (i, a) in cuboids.iter().enumerate() {
+            for b in &cuboids[i + 1..] {
+                assert_eq!(a.intersect(b), None, "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn rectangle_volume() {
+        assert_eq!(rect((0, 3), (0, 1)).volume(), 8);
+        assert_eq!(rect((2, 2), (5, 5)).volume(), 1);
+        assert_eq!(CuboidSet::<2>::new().volume(), 0);
+    }
+
+    #[test]
+    fn rectangle_union() {
+        let a = rect((0, 4), (0, 4));
+        let b = rect((2, 6), (3, 8));
+        let union = a.union(&b);
+
+        assert_disjoint(&union);
+        assert_eq!(union.volume(), 25 + 30 - 6);
+        assert_eq!(points(&union).len() as i64, union.volume());
+        assert!(points(&union).iter().all(|p| a.contains(p) || b.contains(p)));
+    }
+
+    #[test]
+    fn rectangle_difference() {
+        let a = rect((0, 4), (0, 4));
+        let hole = rect((1, 3), (1, 3));
+        let ring = a.difference(&hole);
+
+        assert_disjoint(&ring);
+        assert_eq!(ring.volume(), 25 - 9);
+        assert!(!ring.contains(&[2, 2]));
+        assert!(ring.contains(&[0, 2]) && ring.contains(&[4, 4]));
+        assert_eq!(a.difference(&a).volume(), 0);
+        assert_eq!(a.difference(&rect((7, 9), (7, 9))).volume(), 25);
+    }
+
+    #[test]
+    fn rectangle_intersection() {
+        let a = rect((0, 4), (0, 4)).union(&rect((6, 9), (0, 2)));
+        let b = rect((3, 7), (1, 5));
+        let both = a.intersection(&b);
+
+        assert_disjoint(&both);
+        assert_eq!(both.volume(), 2 * 4 + 2 * 2);
+        assert!(points(&both).iter().all(|p| a.contains(p) && b.contains(p)));
+        assert_eq!(a.count_in(&Cuboid::new([3, 1], [7, 5])), both.volume());
+    }
+
+    #[test]
+    fn rectangles_stay_disjoint() {
+        let mut set = CuboidSet::<2>::new();
+        let ops = [
+            (true, rect((0, 5), (0, 5))),
+            (true, rect((3, 8), (2, 9))),
+            (false, rect((2, 4), (1, 7))),
+            (true, rect((1, 9), (4, 4))),
+            (false, rect((6, 6), (0, 9))),
+        ];
+
+        for (on, r) in &ops {
+            set = if *on { set.union(r) } else { set.difference(r) };
+            assert_disjoint(&set);
+            assert_eq!(points(&set).len() as i64, set.volume());
+        }
+    }
+}
@@ -1,4 +1,7 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io::{BufReader, BufRead};
 
 #[derive(Clone)]
@@ -47,14 +50,6 @@ impl Map {
         Map { map, width, height }
     }
 
-    fn get_right_coords(&self, x:usize, y:usize) -> (usize, usize) {
-        ((x + 1) % self.width, y)
-    }
-
-    fn get_down_coords(&self, x:usize, y:usize) -> (usize, usize) {
-        (x, (y + 1) % self.height)
-    }
-    
     fn get_at(&self, x:usize, y:usize) -> Option<&SeaCucumber> {
         self.map.get(self.index_of(x, y))
     }
@@ -73,77 +68,214 @@ impl Map {
         x + y * self.width
     }
 
-    fn step(&self) -> (Self, bool) {
-        let mut next = Map::new_with_size(self.width, self.height);
-
-        let mut moved = false;
-
-        //Step right
+    fn _display(&self) {
         for y in 0..self.height {
             for x in 0..self.width {
                 match self.get_at(x, y) {
-                    Some(SeaCucumber::Right) => {
-                        let (rx, ry) = self.get_right_coords(x, y);
-                        match self.get_at(rx, ry) {
-                            Some(SeaCucumber::None) => {
-                                next.set_at(rx, ry, SeaCucumber::Right);
-                                moved = true;
-                            },
-                            _ => {
-                                next.set_at(x, y, SeaCucumber::Right);
-                            }
-                        }
-                    },
-                    Some(SeaCucumber::Down) => {
-                        next.set_at(x, y, SeaCucumber::Down);
-                    },
+                    Some(SeaCucumber::Right) => print!(">"),
+                    Some(SeaCucumber::Down) => print!("v"),
+                    Some(SeaCucumber::None) => print!("."),
                     _ => ()
                 }
             }
+            println!("");
         }
+    }
+}
 
-        let int = next.clone();
-        next = Map::new_with_size(int.width, int.height);
+#[derive(Clone, Copy)]
+struct StepStats {
+    east_moved: u32,
+    south_moved: u32
+}
 
-        //Step down
-        for y in 0..int.height {
-            for x in 0..int.width {
-                match int.get_at(x, y) {
-                    Some(SeaCucumber::Down) => {
-                        let (rx, ry) = int.get_down_coords(x, y);
-                        match int.get_at(rx, ry) {
-                            Some(SeaCucumber::None) => {
-                                next.set_at(rx, ry, SeaCucumber::Down);
-                                moved = true;
-                            },
-                            _ => {
-                                next.set_at(x, y, SeaCucumber::Down);
-                            }
-                        }
-                    },
-                    Some(SeaCucumber::Right) => {
-                        next.set_at(x, y, SeaCucumber::Right);
-                    },
+impl StepStats {
+    fn total(&self) -> u32 {
+        self.east_moved + self.south_moved
+    }
+}
+
+enum Outcome {
+    // Nothing moved on the last of these steps
+    Stopped { steps: usize },
+    // The state after step start comes back every length steps, with something moving each time
+    Cycle { start: usize, length: usize }
+}
+
+struct Simulation {
+    history: Vec<StepStats>,
+    outcome: Outcome
+}
+
+// Each herd stored as one bitset per row, so a whole row of sea cucumbers moves with a few shifts and masks
+#[derive(Clone)]
+struct BitMap {
+    east: Vec<u64>,
+    south: Vec<u64>,
+    width: usize,
+    height: usize,
+    words: usize,
+    last_mask: u64,
+    // Scratch space reused across steps
+    movers: Vec<u64>,
+    occupied: Vec<u64>,
+    shifted: Vec<u64>
+}
+
+impl BitMap {
+    fn new(map: &Map) -> Self {
+        let words = map.width.div_ceil(64);
+        let last_mask = match map.width % 64 {
+            0 => !0,
+            r => (1 << r) - 1
+        };
+
+        let mut east = vec![0; words * map.height];
+        let mut south = vec![0; words * map.height];
+
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let bit = 1 << (x % 64);
+                match map.get_at(x, y) {
+                    Some(SeaCucumber::Right) => east[y * words + x / 64] |= bit,
+                    Some(SeaCucumber::Down) => south[y * words + x / 64] |= bit,
                     _ => ()
                 }
             }
         }
 
-        (next, moved)
+        BitMap {
+            east,
+            south,
+            width: map.width,
+            height: map.height,
+            words,
+            last_mask,
+            movers: vec![0; words * map.height],
+            occupied: vec![0; words * map.height],
+            shifted: vec![0; words]
+        }
     }
 
-    fn _display(&self) {
+    fn step(&mut self) -> StepStats {
+        let w = self.words;
+        let mut stats = StepStats { east_moved: 0, south_moved: 0 };
+
+        //Step right
         for y in 0..self.height {
-            for x in 0..self.width {
-                match self.get_at(x, y) {
-                    Some(SeaCucumber::Right) => print!(">"),
-                    Some(SeaCucumber::Down) => print!("v"),
-                    Some(SeaCucumber::None) => print!("."),
-                    _ => ()
+            let row = y * w..(y + 1) * w;
+
+            for i in row.clone() {
+                self.occupied[i] = self.east[i] | self.south[i];
+            }
+
+            // Bit x of shifted says whether cell x + 1 is taken
+            self.rotate_toward_low(row.clone());
+            for (i, s) in row.clone().zip(0..w) {
+                self.movers[i] = self.east[i] & !self.shifted[s];
+                stats.east_moved += self.movers[i].count_ones();
+            }
+
+            self.rotate_toward_high(row.clone());
+            for (i, s) in row.zip(0..w) {
+                self.east[i] = (self.east[i] & !self.movers[i]) | self.shifted[s];
+            }
+        }
+
+        //Step down
+        for i in 0..self.east.len() {
+            self.occupied[i] = self.east[i] | self.south[i];
+        }
+
+        for y in 0..self.height {
+            let below = (y + 1) % self.height * w;
+            for k in 0..w {
+                let i = y * w + k;
+                self.movers[i] = self.south[i] & !self.occupied[below + k];
+                stats.south_moved += self.movers[i].count_ones();
+            }
+        }
+
+        for y in 0..self.height {
+            let above = (y + self.height - 1) % self.height * w;
+            for k in 0..w {
+                let i = y * w + k;
+                self.south[i] = (self.south[i] & !self.movers[i]) | self.movers[above + k];
+            }
+        }
+
+        stats
+    }
+
+    fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.east.hash(&mut hasher);
+        self.south.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn same_state(&self, other: &BitMap) -> bool {
+        self.east == other.east && self.south == other.south
+    }
+
+    fn after(&self, steps: usize) -> BitMap {
+        let mut map = self.clone();
+        for _ in 0..steps {
+            map.step();
+        }
+        map
+    }
+
+    // Moves every bit of movers in the row from x to x + 1, wrapping the last column to the first
+    fn rotate_toward_high(&mut self, row: std::ops::Range<usize>) {
+        let src = &self.movers[row];
+        let last_bit = self.width - 1;
+        let wraps = src[last_bit / 64] & (1 << (last_bit % 64)) != 0;
+
+        let mut carry = 0;
+        for (s, &word) in self.shifted.iter_mut().zip(src) {
+            *s = (word << 1) | carry;
+            carry = word >> 63;
+        }
+
+        self.shifted[self.words - 1] &= self.last_mask;
+        self.shifted[0] |= wraps as u64;
+    }
+
+    // Moves every bit of occupied in the row from x + 1 to x, wrapping the first column to the last
+    fn rotate_toward_low(&mut self, row: std::ops::Range<usize>) {
+        let src = &self.occupied[row];
+        let wraps = src[0] & 1 != 0;
+
+        for k in 0..self.words {
+            let next = if k + 1 < self.words { src[k + 1] << 63 } else { 0 };
+            self.shifted[k] = (src[k] >> 1) | next;
+        }
+
+        if wraps {
+            let last_bit = self.width - 1;
+            self.shifted[last_bit / 64] |= 1 << (last_bit % 64);
+        }
+    }
+}
+
+impl From<&BitMap> for Map {
+    fn from(bits: &BitMap) -> Self {
+        let mut map = Map::new_with_size(bits.width, bits.height);
+
+        for y in 0..bits.height {
+            for x in 0..bits.width {
+                let i = y * bits.words + x / 64;
+                let bit = 1 << (x % 64);
+                if bits.east[i] & bit != 0 {
+                    map.set_at(x, y, SeaCucumber::Right);
+                } else if bits.south[i] & bit != 0 {
+                    map.set_at(x, y, SeaCucumber::Down);
                 }
             }
-            println!("");
         }
+
+        map
     }
 }
 
@@ -157,17 +289,42 @@ fn run_part_01() {
 
     let lines:Vec<String> = reader.lines().map(|x| x.unwrap()).collect();
 
-    let mut map = Map::new_from_lines(lines);
+    let mut map = BitMap::new(&Map::new_from_lines(lines));
+
+    let simulation = simulate(&mut map);
 
-    let mut moved = true;
+    if let Some((step, busiest)) = simulation.history.iter().enumerate().max_by_key(|(_, s)| s.total()) {
+        println!("The busiest step was {} with {} moving east and {} moving south",
+            step + 1, busiest.east_moved, busiest.south_moved);
+    }
 
-    let mut num_steps = 0;
-    while moved {
-        let (new_map, new_moved) = map.step();
-        map = new_map;
-        moved = new_moved;
-        num_steps += 1;
+    match simulation.outcome {
+        Outcome::Stopped { steps } => println!("After {} steps the sea cucumbers stopped moving", steps),
+        Outcome::Cycle { start, length } => println!("The sea cucumbers never stop, cycling every {} steps from step {}", length, start)
     }
+}
+
+// Steps until nothing moves, or until a state repeats, which means it would go on forever
+fn simulate(map: &mut BitMap) -> Simulation {
+    let initial = map.clone();
+    let mut history = Vec::new();
+    // Only a hash of each state is kept, so a match is replayed from the start and compared before it counts
+    let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+    seen.insert(map.state_hash(), vec![0]);
+
+    loop {
+        let stats = map.step();
+        history.push(stats);
 
-    println!("After {} steps the sea cucumbers stopped moving", num_steps);
-}
\ No newline at end of file
+        let steps = history.len();
+        if stats.total() == 0 {
+            return Simulation { history, outcome: Outcome::Stopped { steps } };
+        }
+
+        let candidates = seen.entry(map.state_hash()).or_default();
+        if let Some(&start) = candidates.iter().find(|&&s| initial.after(s).same_state(map)) {
+            return Simulation { history, outcome: Outcome::Cycle { start, length: steps - start } };
+        }
+        candidates.push(steps);
+    }
+}
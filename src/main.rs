@@ -22,6 +22,8 @@ pub mod day21;
 pub mod day22;
 pub mod day23;
 pub mod day25;
+pub mod matrix;
+pub mod ocr;
 
 use std::env;
 use std::time::Instant;
@@ -0,0 +1,84 @@
+// Square matrix over u128, either overflow-checked or reduced modulo some value below 2^64
+#[derive(Clone)]
+pub struct Matrix {
+    pub n: usize,
+    pub cells: Vec<u128>
+}
+
+impl Matrix {
+    pub fn zero(n: usize) -> Self {
+        Matrix { n, cells: vec![0; n * n] }
+    }
+
+    pub fn identity(n: usize) -> Self {
+        let mut m = Matrix::zero(n);
+        for i in 0..n {
+            m.cells[i * n + i] = 1;
+        }
+        m
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> u128 {
+        self.cells[row * self.n + col]
+    }
+
+    pub fn mul(&self, other: &Matrix, modulus: Option<u128>) -> Option<Matrix> {
+        let n = self.n;
+        let mut result = Matrix::zero(n);
+
+        for i in 0..n {
+            for k in 0..n {
+                let a = self.get(i, k);
+                if a == 0 {
+                    continue;
+                }
+
+                for j in 0..n {
+                    let b = other.get(k, j);
+                    let cell = &mut result.cells[i * n + j];
+                    *cell = accumulate(*cell, a, b, modulus)?;
+                }
+            }
+        }
+
+        Some(result)
+    }
+
+    pub fn pow(&self, mut exp: u64, modulus: Option<u128>) -> Option<Matrix> {
+        let mut result = Matrix::identity(self.n);
+        let mut base = self.clone();
+
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.mul(&base, modulus)?;
+            }
+
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base, modulus)?;
+            }
+        }
+
+        Some(result)
+    }
+
+    pub fn apply(&self, v: &[u128], modulus: Option<u128>) -> Option<Vec<u128>> {
+        let mut result = vec![0; self.n];
+
+        for (i, cell) in result.iter_mut().enumerate() {
+            for (j, &x) in v.iter().enumerate() {
+                *cell = accumulate(*cell, self.get(i, j), x, modulus)?;
+            }
+        }
+
+        Some(result)
+    }
+}
+
+// acc + a * b, or None if that overflows when no modulus is given
+pub fn accumulate(acc: u128, a: u128, b: u128, modulus: Option<u128>) -> Option<u128> {
+    match modulus {
+        Some(m) => Some((acc + a * b % m) % m),
+        None => acc.checked_add(a.checked_mul(b)?)
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::HashSet;
+
+const GLYPH_WIDTH: i32 = 4;
+const GLYPH_HEIGHT: i32 = 6;
+// Letters sit one empty column apart, starting from x = 0
+const GLYPH_STRIDE: i32 = GLYPH_WIDTH + 1;
+
+// The 4x6 dot-matrix letters the puzzles draw
+const FONT: [(char, [&str; 6]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+// Reads a row of letters from a set of lit (x, y) dots, or None if any glyph isn't in the font
+pub fn recognize(dots: &HashSet<(i32, i32)>) -> Option<String> {
+    let min_x = dots.iter().map(|d| d.0).min()?;
+    let min_y = dots.iter().map(|d| d.1).min()?;
+    let max_x = dots.iter().map(|d| d.0).max()?;
+    let max_y = dots.iter().map(|d| d.1).max()?;
+
+    if max_y - min_y + 1 != GLYPH_HEIGHT {
+        return None;
+    }
+
+    // Some letters leave their first column empty, so snap to the letter grid rather than the leftmost dot
+    let left = min_x - min_x.rem_euclid(GLYPH_STRIDE);
+    let num_letters = (max_x - left) / GLYPH_STRIDE + 1;
+
+    (0..num_letters)
+        .map(|i| recognize_glyph(dots, left + i * GLYPH_STRIDE, min_y))
+        .collect()
+}
+
+fn recognize_glyph(dots: &HashSet<(i32, i32)>, left: i32, top: i32) -> Option<char> {
+    FONT.iter()
+        .find(|(_, rows)| {
+            rows.iter().enumerate().all(|(y, row)| {
+                row.chars().enumerate().all(|(x, c)| (c == '#') == dots.contains(&(left + x as i32, top + y as i32)))
+            })
+        })
+        .map(|(letter, _)| *letter)
+}